@@ -1,6 +1,7 @@
 import init, {
   CanvasDriven,
   CanvasDrivenArgs,
+  FrameRenderer,
   TreeType,
   Pendulum,
 } from "./pkg/fluid";
@@ -10,7 +11,7 @@ init().then(async () => {
   document.body.appendChild(canvas);
   canvas.width = canvas.offsetWidth * devicePixelRatio;
   canvas.height = canvas.offsetHeight * devicePixelRatio;
-  drawPendulum(canvas);
+  drawParticlesInWorker(canvas);
 });
 
 async function drawPendulum(canvas: HTMLCanvasElement) {
@@ -42,7 +43,7 @@ async function drawPendulum(canvas: HTMLCanvasElement) {
 }
 
 function raf() {
-  return new Promise((resolve) => requestAnimationFrame(resolve));
+  return new Promise<number>((resolve) => requestAnimationFrame(resolve));
 }
 
 async function drawParticles(canvas: HTMLCanvasElement) {
@@ -67,15 +68,62 @@ async function drawParticles(canvas: HTMLCanvasElement) {
   canvas.addEventListener("mouseup", (e) => {
     mousePos.isPresing = false;
   });
+  let last = await raf();
   while (true) {
     driven.update_mouse_pos(mousePos.x, mousePos.y, mousePos.isPresing);
-    driven.evolve(4);
     ctx.clearRect(0, 0, canvas.width, canvas.height);
     driven.draw(ctx);
-    await raf();
+    const now = await raf();
+    driven.advance((now - last) / 1000);
+    last = now;
   }
 }
 
+// Same as drawParticles, but the simulation steps in a worker and only the drawing
+// happens here.
+function drawParticlesInWorker(canvas: HTMLCanvasElement) {
+  const ctx = canvas.getContext("2d")!;
+  const renderer = FrameRenderer.new();
+  const worker = new Worker(new URL("./worker.ts", import.meta.url), {
+    type: "module",
+  });
+  worker.postMessage({
+    type: "start",
+    width: canvas.width,
+    height: canvas.height,
+    particles: 5_000,
+  });
+  const mousePos = { x: 0, y: 0, isPressing: false };
+  const sendMouse = () => worker.postMessage({ type: "mouse", ...mousePos });
+  canvas.addEventListener("mousemove", (e) => {
+    mousePos.x = e.offsetX * devicePixelRatio;
+    mousePos.y = e.offsetY * devicePixelRatio;
+    sendMouse();
+  });
+  canvas.addEventListener("mouseleave", () => {
+    worker.postMessage({ type: "leave" });
+  });
+  canvas.addEventListener("mousedown", () => {
+    mousePos.isPressing = true;
+    sendMouse();
+  });
+  canvas.addEventListener("mouseup", () => {
+    mousePos.isPressing = false;
+    sendMouse();
+  });
+  // a new frame is only asked for once the previous one is drawn
+  let last = performance.now();
+  worker.onmessage = (e: MessageEvent<Uint8Array>) => {
+    renderer.load(e.data);
+    requestAnimationFrame((now) => {
+      ctx.clearRect(0, 0, canvas.width, canvas.height);
+      renderer.draw(ctx);
+      worker.postMessage({ type: "frame", dt: (now - last) / 1000 });
+      last = now;
+    });
+  };
+}
+
 async function awaitClick() {
   return new Promise((resolve) => {
     document.addEventListener("click", resolve, { once: true });
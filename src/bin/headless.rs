@@ -0,0 +1,101 @@
+//! Runs the fluid without a canvas and dumps the particles of every k-th frame.
+//!
+//! ```text
+//! headless [--particles N] [--frames N] [--steps N] [--every K] [--seed S]
+//!          [--solver force|pbf|dfsph] [--format csv|vtk|ply|ply-binary] [--out DIR]
+//! ```
+
+use std::{env, error::Error, fs, path::PathBuf, process};
+
+use fluid::{CanvasDriven, CanvasDrivenArgs, ExportFormat, SimulationParams, Solver};
+
+struct Options {
+    particles: usize,
+    frames: usize,
+    /// Simulation steps per frame.
+    steps: usize,
+    every: usize,
+    seed: u64,
+    solver: Solver,
+    format: ExportFormat,
+    out: PathBuf,
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        particles: 1000,
+        frames: 300,
+        steps: 1,
+        every: 10,
+        seed: 0,
+        solver: Solver::Force,
+        format: ExportFormat::Vtk,
+        out: PathBuf::from("frames"),
+    };
+    let number = |value: &str| value.parse().map_err(|_| format!("invalid number {value}"));
+    for pair in args.chunks(2) {
+        let [flag, value] = pair else {
+            return Err(format!("missing value for {}", pair[0]));
+        };
+        match flag.as_str() {
+            "--particles" => options.particles = number(value)?,
+            "--frames" => options.frames = number(value)?,
+            "--steps" => options.steps = number(value)?,
+            "--every" => options.every = number(value)?.max(1),
+            "--seed" => options.seed = number(value)? as u64,
+            "--solver" => {
+                options.solver = match value.as_str() {
+                    "force" => Solver::Force,
+                    "pbf" => Solver::PositionBased,
+                    "dfsph" => Solver::Incompressible,
+                    _ => return Err(format!("unknown solver {value}")),
+                }
+            }
+            "--format" => {
+                options.format = match value.as_str() {
+                    "csv" => ExportFormat::Csv,
+                    "vtk" => ExportFormat::Vtk,
+                    "ply" => ExportFormat::PlyAscii,
+                    "ply-binary" => ExportFormat::PlyBinary,
+                    _ => return Err(format!("unknown format {value}")),
+                }
+            }
+            "--out" => options.out = PathBuf::from(value),
+            _ => return Err(format!("unknown option {flag}")),
+        }
+    }
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&options.out)?;
+    let mut params = SimulationParams::default();
+    params.solver = options.solver;
+    let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+        particles: options.particles,
+        params,
+        seed: Some(options.seed),
+        ..CanvasDrivenArgs::default()
+    })?;
+    for frame in 0..options.frames {
+        if frame % options.every == 0 {
+            let name = format!("frame_{frame:05}.{}", options.format.extension());
+            let mut file = fs::File::create(options.out.join(name))?;
+            canvas.write_particles(options.format, &mut file)?;
+        }
+        canvas.evolve(options.steps)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse(&args).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        process::exit(2);
+    });
+    if let Err(error) = run(&options) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,110 @@
+//! Errors of the exported API, thrown in JS as `Error`s with the same message.
+
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+use crate::particles::SceneError;
+use crate::snapshot::SnapshotError;
+
+#[derive(Debug, PartialEq)]
+pub enum FluidError {
+    /// An argument outside of its valid range, e.g. a NaN coordinate or a zero size.
+    InvalidArgument {
+        name: &'static str,
+        reason: &'static str,
+    },
+    /// `draw` was given something other than a `CanvasRenderingContext2d`.
+    NotACanvasContext,
+    /// A canvas call threw.
+    Canvas(String),
+    Snapshot(SnapshotError),
+    Scene(SceneError),
+    /// Svg path data that can't be parsed.
+    Path(String),
+    /// No pendulum at this index of a scene.
+    MissingPendulum(usize),
+    /// The distance field boundary was configured before any shape was added to it.
+    MissingSdf,
+    /// A step left a particle, or a pendulum ball, with a non-finite position or velocity
+    /// after `time` simulated seconds of an `evolve` call, which was undone.
+    Unstable {
+        time: f64,
+        particle: usize,
+    },
+}
+
+impl fmt::Display for FluidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FluidError::InvalidArgument { name, reason } => write!(f, "{name} {reason}"),
+            FluidError::NotACanvasContext => write!(f, "not a CanvasRenderingContext2D"),
+            FluidError::Canvas(message) => write!(f, "canvas error: {message}"),
+            FluidError::Snapshot(error) => write!(f, "{error}"),
+            FluidError::Scene(error) => write!(f, "{error}"),
+            FluidError::Path(message) => write!(f, "invalid svg path: {message}"),
+            FluidError::MissingPendulum(index) => write!(f, "no pendulum {index} in the scene"),
+            FluidError::MissingSdf => write!(f, "no distance field boundary, add a shape first"),
+            FluidError::Unstable { time, particle } => write!(
+                f,
+                "simulation became unstable after {time}s, particle {particle} is not finite"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FluidError {}
+
+impl From<SnapshotError> for FluidError {
+    fn from(error: SnapshotError) -> Self {
+        FluidError::Snapshot(error)
+    }
+}
+
+impl From<SceneError> for FluidError {
+    fn from(error: SceneError) -> Self {
+        FluidError::Scene(error)
+    }
+}
+
+impl From<FluidError> for JsValue {
+    fn from(error: FluidError) -> Self {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
+
+/// Checks that every value is finite.
+pub(crate) fn finite(name: &'static str, values: &[f64]) -> Result<(), FluidError> {
+    if values.iter().all(|value| value.is_finite()) {
+        Ok(())
+    } else {
+        Err(FluidError::InvalidArgument {
+            name,
+            reason: "must be finite",
+        })
+    }
+}
+
+/// Checks that the value is finite and greater than zero.
+pub(crate) fn positive(name: &'static str, value: f64) -> Result<(), FluidError> {
+    if value.is_finite() && value > 0. {
+        Ok(())
+    } else {
+        Err(FluidError::InvalidArgument {
+            name,
+            reason: "must be a positive number",
+        })
+    }
+}
+
+/// Checks that the value is finite and not negative.
+pub(crate) fn non_negative(name: &'static str, value: f64) -> Result<(), FluidError> {
+    if value.is_finite() && value >= 0. {
+        Ok(())
+    } else {
+        Err(FluidError::InvalidArgument {
+            name,
+            reason: "must be a non-negative number",
+        })
+    }
+}
@@ -1,3 +1,6 @@
+mod error;
 mod particles;
+mod snapshot;
 mod triple_pendulum;
+pub use error::FluidError;
 pub use particles::*;
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+/// Written as `[r, g, b, a]` in scenes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(from = "[u8; 4]", into = "[u8; 4]")]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl From<[u8; 4]> for Rgba {
+    fn from([r, g, b, a]: [u8; 4]) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+}
+
+impl From<Rgba> for [u8; 4] {
+    fn from(c: Rgba) -> [u8; 4] {
+        [c.r, c.g, c.b, c.a]
+    }
+}
+
+impl Rgba {
+    pub const WHITE: Rgba = Rgba::new(255, 255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    pub fn to_css(self) -> String {
+        format!(
+            "rgba({}, {}, {}, {})",
+            self.r,
+            self.g,
+            self.b,
+            self.a as f64 / 255.
+        )
+    }
+}
+
+/// Names of the custom scalar channels every particle of a world carries,
+/// `Particle::channels[i]` holds the value of the channel named `names[i]`.
+#[derive(Clone, Debug, Default)]
+pub struct Channels {
+    names: Vec<String>,
+}
+
+impl Channels {
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    /// Returns the index of the channel, registering it when it is new.
+    pub fn register(&mut self, name: &str) -> usize {
+        if let Some(index) = self.index_of(name) {
+            return index;
+        }
+        self.names.push(name.to_string());
+        self.names.len() - 1
+    }
+}
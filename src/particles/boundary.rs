@@ -0,0 +1,101 @@
+use kurbo::{ParamCurve, ParamCurveArclen, Point};
+
+use super::{
+    particle::{GeoQuery, Particle, ParticleKind, World, PARTICLE_RADIUS},
+    sph::{self, SMOOTHING_RADIUS},
+    v2::V2,
+};
+
+/// Distance between consecutive boundary particles along a wall.
+const BOUNDARY_SPACING: f64 = PARTICLE_RADIUS / 2.;
+const ARCLEN_ACCURACY: f64 = 0.1;
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// Samples the solid domain walls and the obstacle outlines with boundary particles
+    /// and computes their volumes, `1 / sum W` over the other boundary particles.
+    pub(super) fn sample_boundary(&self) -> Vec<Particle> {
+        let mut positions = Vec::new();
+        self.walls()
+            .into_iter()
+            .filter(|wall| wall.is_solid())
+            .for_each(|wall| sample_line(&wall.from, &wall.to, &mut positions));
+        self.obstacles.iter().for_each(|obstacle| {
+            obstacle.to_path().segments().for_each(|segment| {
+                let length = segment.arclen(ARCLEN_ACCURACY);
+                let n = (length / BOUNDARY_SPACING).ceil().max(1.) as usize;
+                (0..n).for_each(|i| {
+                    let Point { x, y } = segment.eval(i as f64 / n as f64);
+                    positions.push(V2::new(x, y));
+                });
+            });
+        });
+
+        let samples: Vec<Particle> = positions
+            .into_iter()
+            .map(|position| Particle::new(position, V2::new(0., 0.)))
+            .collect();
+        let tree = T::from_vec(samples.clone(), self.dimensions.x.max(self.dimensions.y));
+        samples
+            .into_iter()
+            .map(|mut particle| {
+                let mut number_density = 0.;
+                tree.query_distance(&particle.position, SMOOTHING_RADIUS, |other| {
+                    number_density += sph::poly6(particle.position.distance_to(&other.position));
+                });
+                particle.kind = ParticleKind::Boundary {
+                    volume: 1. / number_density.max(1e-9),
+                };
+                particle
+            })
+            .collect()
+    }
+}
+
+fn sample_line(from: &V2, to: &V2, positions: &mut Vec<V2>) {
+    let delta = to.sub(from);
+    let n = (delta.len() / BOUNDARY_SPACING).ceil().max(1.) as usize;
+    (0..n).for_each(|i| positions.push(*from + (i as f64 / n as f64) * delta));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::{
+        gravity::Gravity,
+        hash_grid::HashGrid,
+        obstacles::{Obstacle, ObstacleShape},
+        params::WallMode,
+    };
+
+    #[test]
+    fn solid_walls_and_obstacle_outlines_are_sampled() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(100., 100.), Gravity::Zero, 0);
+        let walls = world.sample_boundary();
+        assert_eq!(walls.len(), 4 * (100. / BOUNDARY_SPACING) as usize);
+        assert!(walls.iter().all(|p| {
+            let on_wall = [p.position.x, p.position.y]
+                .iter()
+                .any(|c| c.abs() < 1e-9 || (c - 100.).abs() < 1e-9);
+            on_wall && matches!(p.kind, ParticleKind::Boundary { volume } if volume > 0.)
+        }));
+
+        world.params.left_wall.mode = WallMode::Open;
+        assert_eq!(
+            world.sample_boundary().len(),
+            walls.len() - (100. / BOUNDARY_SPACING) as usize
+        );
+
+        let circle = kurbo::Circle::new((50., 50.), 10.);
+        world.add_obstacle(Obstacle::new(ObstacleShape::Circle(circle), 1., 0.));
+        let center = V2::new(50., 50.);
+        let outline: Vec<Particle> = world
+            .sample_boundary()
+            .into_iter()
+            .filter(|p| p.position.distance_to(&center) < 20.)
+            .collect();
+        assert!(outline.len() >= (std::f64::consts::TAU * 10. / BOUNDARY_SPACING) as usize);
+        assert!(outline
+            .iter()
+            .all(|p| (p.position.distance_to(&center) - 10.).abs() < 0.1));
+    }
+}
@@ -0,0 +1,119 @@
+use super::{attributes::Channels, particle::Particle};
+
+/// Version of the layout of the particle buffers, bumped on any change to it.
+pub const LAYOUT_VERSION: u32 = 1;
+
+/// Attributes of every particle, followed by its custom channels.
+const ATTRIBUTES: [&str; 7] = ["mass", "phase", "density", "r", "g", "b", "a"];
+
+/// Flat copies of the particle state that JS reads through views into wasm memory:
+/// `positions` and `velocities` hold `x, y` pairs, `attributes` holds `stride` values per
+/// particle, named by `attribute_names`.
+#[derive(Default)]
+pub(super) struct ParticleBuffers {
+    pub positions: Vec<f32>,
+    pub velocities: Vec<f32>,
+    pub attributes: Vec<f64>,
+    pub stride: usize,
+    /// Number of syncs that changed the buffers.
+    pub changes: u32,
+}
+
+impl ParticleBuffers {
+    /// Copies the particles, counting a change when anything differs from the last copy.
+    /// The buffers are overwritten in place and only reallocated when they grow.
+    pub fn sync(&mut self, particles: &[Particle], channels: &Channels) -> u32 {
+        let positions = particles
+            .iter()
+            .flat_map(|p| [p.position.x as f32, p.position.y as f32]);
+        let velocities = particles
+            .iter()
+            .flat_map(|p| [p.velocity.x as f32, p.velocity.y as f32]);
+        let stride = ATTRIBUTES.len() + channels.len();
+        let attributes = particles.iter().flat_map(|p| {
+            let color = p.color;
+            [
+                p.mass,
+                p.phase as f64,
+                p.sph.number_density,
+                color.r as f64,
+                color.g as f64,
+                color.b as f64,
+                color.a as f64,
+            ]
+            .into_iter()
+            .chain((0..channels.len()).map(|i| p.channels.get(i).copied().unwrap_or(0.)))
+        });
+        let changed = [
+            overwrite(&mut self.positions, positions),
+            overwrite(&mut self.velocities, velocities),
+            overwrite(&mut self.attributes, attributes),
+            stride != self.stride,
+        ]
+        .contains(&true);
+        self.stride = stride;
+        if changed {
+            self.changes = self.changes.wrapping_add(1);
+        }
+        self.changes
+    }
+
+    pub fn attribute_names(channels: &Channels) -> Vec<String> {
+        ATTRIBUTES
+            .iter()
+            .map(|name| name.to_string())
+            .chain(channels.names().iter().cloned())
+            .collect()
+    }
+}
+
+/// Writes the values over the buffer, returns whether anything changed.
+fn overwrite<T: Copy + PartialEq>(buffer: &mut Vec<T>, values: impl Iterator<Item = T>) -> bool {
+    let mut changed = false;
+    let mut len = 0;
+    for (i, value) in values.enumerate() {
+        match buffer.get_mut(i) {
+            Some(old) if *old == value => {}
+            Some(old) => {
+                *old = value;
+                changed = true;
+            }
+            None => {
+                buffer.push(value);
+                changed = true;
+            }
+        }
+        len = i + 1;
+    }
+    if len < buffer.len() {
+        buffer.truncate(len);
+        changed = true;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::v2::V2;
+    use super::*;
+
+    #[test]
+    fn sync_counts_changes() {
+        let mut channels = Channels::default();
+        channels.register("temperature");
+        let mut particles = vec![Particle::new(V2::new(1., 2.), V2::new(3., 4.))];
+        let mut buffers = ParticleBuffers::default();
+        assert_eq!(buffers.sync(&particles, &channels), 1);
+        assert_eq!(buffers.positions, [1., 2.]);
+        assert_eq!(buffers.stride, 8);
+        assert_eq!(buffers.attributes[..3], [1., 0., 0.]);
+        assert_eq!(buffers.sync(&particles, &channels), 1);
+
+        particles[0].channels = vec![20.];
+        assert_eq!(buffers.sync(&particles, &channels), 2);
+        assert_eq!(buffers.attributes[7], 20.);
+        particles.clear();
+        assert_eq!(buffers.sync(&particles, &channels), 3);
+        assert!(buffers.positions.is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+use super::{
+    neighbours::Neighbour,
+    particle::{GeoQuery, Particle, World},
+    sph,
+    v2::V2,
+};
+
+/// The solvers always run at least this many iterations, so the average error
+/// cannot look converged before the pressure had any effect.
+const MIN_ITERATIONS: usize = 2;
+
+enum PressurePass {
+    /// Drives the predicted density towards the rest density.
+    Density,
+    /// Drives the velocity divergence towards 0.
+    Divergence,
+}
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// One step of Divergence-Free SPH (Bender & Koschier 2015): a constant density
+    /// solve on the predicted velocities, then a divergence free solve after advection.
+    /// The iterations and the worst errors are added to `stats`.
+    pub(super) fn dfsph_step(&mut self) {
+        let dt = self.params.dt;
+        self.update_tree();
+        let neighbours = self.find_neighbours();
+        self.compute_factors(&neighbours);
+        if self.params.surface_tension > 0. {
+            self.compute_normals(&neighbours);
+        }
+        let accelerations: Vec<V2> = self
+            .particles
+            .iter()
+            .map(|p| self.calc_external_acc(p))
+            .collect();
+        self.particles
+            .iter_mut()
+            .zip(accelerations)
+            .for_each(|(particle, acc)| particle.velocity = particle.velocity + dt * acc);
+        self.apply_xsph_viscosity(&neighbours);
+
+        let (density_iterations, density_error) =
+            self.solve_pressure(dt, PressurePass::Density, &neighbours);
+
+        let mut particles = std::mem::take(&mut self.particles);
+        particles.iter_mut().for_each(|particle| {
+            particle.position = particle.position + dt * particle.velocity;
+            self.resolve_collisions(particle);
+        });
+        self.particles = particles;
+        self.update_tree();
+        let neighbours = self.find_neighbours();
+        self.compute_factors(&neighbours);
+
+        let (divergence_iterations, divergence_error) =
+            self.solve_pressure(dt, PressurePass::Divergence, &neighbours);
+
+        self.stats.density_iterations += density_iterations;
+        self.stats.density_error = self.stats.density_error.max(density_error);
+        self.stats.divergence_iterations += divergence_iterations;
+        self.stats.divergence_error = self.stats.divergence_error.max(divergence_error);
+    }
+
+    /// Number densities and the DFSPH factor of the number density formulation,
+    /// `alpha_i = n_i / (|sum grad W|^2 / m_i + sum |grad W|^2 / m_j)`.
+    fn compute_factors(&mut self, neighbours: &[Vec<Neighbour>]) {
+        let factors: Vec<(f64, f64)> = self
+            .particles
+            .iter()
+            .zip(neighbours)
+            .map(|(p, neighbours)| {
+                let mut number_density = 0.;
+                let mut gradient_i = V2::new(0., 0.);
+                let mut gradient_sum = 0.;
+                neighbours.iter().for_each(|neighbour| {
+                    let other = self.neighbour(neighbour);
+                    let r = p.position.sub(&self.neighbour_position(neighbour));
+                    let weight = self.neighbour_weight(other);
+                    number_density += weight * sph::poly6(r.len());
+                    let gradient = weight * sph::spiky_gradient(&r);
+                    gradient_i = gradient_i + gradient;
+                    if !other.is_boundary() {
+                        gradient_sum += gradient.norm_sqr() / other.mass;
+                    }
+                });
+                let denominator = gradient_i.norm_sqr() / p.mass + gradient_sum;
+                let alpha = if denominator > 1e-9 {
+                    number_density / denominator
+                } else {
+                    0.
+                };
+                (number_density, alpha)
+            })
+            .collect();
+        self.particles
+            .iter_mut()
+            .zip(factors)
+            .for_each(|(particle, (number_density, alpha))| {
+                particle.sph.number_density = number_density;
+                particle.sph.alpha = alpha;
+            });
+    }
+
+    /// Jacobi style pressure solve shared by the density and divergence passes,
+    /// returns the iteration count and the average error relative to the rest density.
+    fn solve_pressure(
+        &mut self,
+        dt: f64,
+        pass: PressurePass,
+        neighbours: &[Vec<Neighbour>],
+    ) -> (usize, f64) {
+        let rest_number_density = self.rest_number_density;
+        let tolerance = match pass {
+            PressurePass::Density => self.params.density_tolerance,
+            PressurePass::Divergence => self.params.divergence_tolerance,
+        };
+        let mut iterations = 0;
+        let mut error = 0.;
+        while iterations < self.params.max_pressure_iterations {
+            let values: Vec<f64> = self
+                .particles
+                .iter()
+                .zip(neighbours)
+                .map(|(p, neighbours)| {
+                    let rate = neighbours.iter().fold(0., |rate, neighbour| {
+                        let other = self.neighbour(neighbour);
+                        let r = p.position.sub(&self.neighbour_position(neighbour));
+                        let gradient = sph::spiky_gradient(&r);
+                        let v = p.velocity.sub(&other.velocity);
+                        rate + self.neighbour_weight(other) * (v.x * gradient.x + v.y * gradient.y)
+                    });
+                    match pass {
+                        PressurePass::Density => {
+                            let predicted = p.sph.number_density + dt * rate;
+                            (predicted - rest_number_density).max(0.) / (dt * dt)
+                        }
+                        PressurePass::Divergence => rate.max(0.) / dt,
+                    }
+                })
+                .collect();
+            let total_error: f64 = values.iter().sum();
+            self.particles
+                .iter_mut()
+                .zip(values)
+                .for_each(|(particle, value)| particle.sph.kappa = value * particle.sph.alpha);
+            iterations += 1;
+            let n = self.particles.len().max(1) as f64;
+            error = total_error * dt * dt / (n * rest_number_density);
+            if error <= tolerance && iterations >= MIN_ITERATIONS {
+                break;
+            }
+            let deltas: Vec<V2> = self
+                .particles
+                .iter()
+                .zip(neighbours)
+                .map(|(p, neighbours)| {
+                    let delta = neighbours.iter().fold(V2::new(0., 0.), |delta, neighbour| {
+                        let other = self.neighbour(neighbour);
+                        let r = p.position.sub(&self.neighbour_position(neighbour));
+                        let k = p.sph.kappa / p.sph.number_density.max(1e-9)
+                            + other.sph.kappa / other.sph.number_density.max(1e-9);
+                        delta + (self.neighbour_weight(other) * k) * sph::spiky_gradient(&r)
+                    });
+                    dt / p.mass * delta
+                })
+                .collect();
+            self.particles
+                .iter_mut()
+                .zip(deltas)
+                .for_each(|(particle, delta)| particle.velocity = particle.velocity.sub(&delta));
+        }
+        (iterations, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::{
+        gravity::Gravity, hash_grid::HashGrid, params::Solver, particle::PARTICLE_RADIUS,
+    };
+
+    #[test]
+    fn pressure_solves_converge_and_add_up_over_a_call() {
+        let gravity = Gravity::Uniform(V2::new(0., 100.));
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(100., 100.), gravity, 0);
+        world.params.solver = Solver::Incompressible;
+        let block = kurbo::Rect::new(20., 40., 80., 100.);
+        world.add_particles_in_shape(&block, 0.8 * PARTICLE_RADIUS);
+
+        // the block is packed tighter than at rest, so the density solve has work to do
+        world.checked_evolve(5).unwrap();
+        let stats = world.stats;
+        let max_iterations = 5 * world.params.max_pressure_iterations;
+        assert!(stats.density_iterations > 5 * MIN_ITERATIONS);
+        assert!(stats.density_iterations < max_iterations);
+        assert!(stats.divergence_iterations >= 5 * MIN_ITERATIONS);
+        assert!(stats.divergence_iterations < max_iterations);
+        assert!(stats.density_error > 0.);
+        assert!(stats.density_error <= world.params.density_tolerance);
+        assert!(stats.divergence_error <= world.params.divergence_tolerance);
+
+        world.checked_evolve(1).unwrap();
+        assert!(world.stats.density_iterations <= world.params.max_pressure_iterations);
+    }
+}
@@ -0,0 +1,199 @@
+use kurbo::{Circle, Rect, Shape};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::error::{finite, non_negative, positive, FluidError};
+
+use super::{
+    particle::{GeoQuery, Particle, World},
+    phases,
+    rng::Rng,
+    v2::V2,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmitterShape {
+    Point(V2),
+    /// Particles start anywhere along the segment.
+    Line(V2, V2),
+    /// A segment of `width` centered on the position, across the emission velocity.
+    Nozzle {
+        position: V2,
+        width: f64,
+    },
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default = "EmitterParams::default", deny_unknown_fields)]
+pub struct EmitterParams {
+    pub velocity_x: f64,
+    pub velocity_y: f64,
+    /// Particles emitted per simulated second.
+    pub rate: f64,
+    /// Angle in radians of the cone the velocity is randomly rotated within.
+    pub spread: f64,
+    /// Phase of the emitted particles.
+    pub phase: u32,
+}
+
+#[wasm_bindgen]
+impl EmitterParams {
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Self {
+        EmitterParams {
+            velocity_x: 0.,
+            velocity_y: 50.,
+            rate: 100.,
+            spread: 0.,
+            phase: 0,
+        }
+    }
+}
+
+impl EmitterShape {
+    pub(crate) fn validate(&self) -> Result<(), FluidError> {
+        match self {
+            EmitterShape::Point(position) => finite("position", &[position.x, position.y]),
+            EmitterShape::Line(a, b) => finite("line", &[a.x, a.y, b.x, b.y]),
+            EmitterShape::Nozzle { position, width } => {
+                finite("position", &[position.x, position.y])?;
+                positive("width", *width)
+            }
+        }
+    }
+}
+
+impl EmitterParams {
+    /// `phases` is the number of registered phases.
+    pub(crate) fn validate(&self, phases: usize) -> Result<(), FluidError> {
+        finite("velocity", &[self.velocity_x, self.velocity_y])?;
+        non_negative("rate", self.rate)?;
+        non_negative("spread", self.spread)?;
+        phases::registered(self.phase, phases)
+    }
+}
+
+/// Injects particles at a constant rate, like a faucet.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    pub shape: EmitterShape,
+    pub params: EmitterParams,
+    /// Fraction of a particle carried over to the next step.
+    pub(super) pending: f64,
+}
+
+impl Emitter {
+    pub fn new(shape: EmitterShape, params: EmitterParams) -> Emitter {
+        Emitter {
+            shape,
+            params,
+            pending: 0.,
+        }
+    }
+}
+
+/// Region deleting every particle that enters it, like a drain.
+#[derive(Clone, Debug)]
+pub enum Sink {
+    Circle(Circle),
+    Rect(Rect),
+}
+
+impl Sink {
+    pub fn contains(&self, point: &V2) -> bool {
+        let point = (point.x, point.y).into();
+        match self {
+            Sink::Circle(circle) => circle.contains(point),
+            Sink::Rect(rect) => rect.contains(point),
+        }
+    }
+}
+
+impl<T: GeoQuery<Particle>> World<T> {
+    pub fn add_emitter(&mut self, emitter: Emitter) -> usize {
+        self.emitters.push(emitter);
+        self.emitters.len() - 1
+    }
+
+    pub fn remove_emitter(&mut self, index: usize) {
+        if index < self.emitters.len() {
+            self.emitters.remove(index);
+        }
+    }
+
+    pub fn add_sink(&mut self, sink: Sink) -> usize {
+        self.sinks.push(sink);
+        self.sinks.len() - 1
+    }
+
+    pub fn remove_sink(&mut self, index: usize) {
+        if index < self.sinks.len() {
+            self.sinks.remove(index);
+        }
+    }
+
+    /// Runs the emitters and sinks for one step.
+    pub(super) fn apply_emitters_and_sinks(&mut self) {
+        let dt = self.params.dt;
+        let mut emitted = Vec::new();
+        for emitter in self.emitters.iter_mut() {
+            emitter.pending += emitter.params.rate * dt;
+            while emitter.pending >= 1. {
+                emitter.pending -= 1.;
+                emitted.push((emit(emitter, &mut self.rng), emitter.params.phase));
+            }
+        }
+        emitted.into_iter().for_each(|(particle, phase)| {
+            self.push_particle(particle);
+            self.set_particle_phase(self.particles.len() - 1, phase);
+        });
+        if !self.sinks.is_empty() {
+            let sinks = &self.sinks;
+            self.particles
+                .retain(|particle| !sinks.iter().any(|sink| sink.contains(&particle.position)));
+        }
+    }
+}
+
+fn emit(emitter: &Emitter, rng: &mut Rng) -> Particle {
+    let params = &emitter.params;
+    let velocity = V2::new(params.velocity_x, params.velocity_y);
+    let position = match &emitter.shape {
+        EmitterShape::Point(position) => *position,
+        EmitterShape::Line(from, to) => *from + rng.next_f64() * to.sub(from),
+        EmitterShape::Nozzle { position, width } => {
+            let across = V2::new(-velocity.y, velocity.x).normalized();
+            *position + (rng.range(-0.5, 0.5) * width) * across
+        }
+    };
+    let angle = rng.range(-0.5, 0.5) * params.spread;
+    let (sin, cos) = angle.sin_cos();
+    let velocity = V2::new(
+        velocity.x * cos - velocity.y * sin,
+        velocity.x * sin + velocity.y * cos,
+    );
+    Particle::new(position, velocity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::{gravity::Gravity, hash_grid::HashGrid};
+
+    #[test]
+    fn emitter_rate_and_sink() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(200., 200.), Gravity::Zero, 7);
+        let params = EmitterParams {
+            rate: 250.,
+            ..EmitterParams::default()
+        };
+        world.add_emitter(Emitter::new(EmitterShape::Point(V2::new(20., 20.)), params));
+        world.checked_evolve(10).unwrap();
+        assert_eq!(world.particles.len(), 25);
+        world.add_sink(Sink::Rect(Rect::new(0., 0., 200., 200.)));
+        world.checked_evolve(1).unwrap();
+        assert!(world.particles.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{self, Write};
+
+use wasm_bindgen::prelude::*;
+
+use super::{attributes::Channels, particle::Particle};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    /// Legacy VTK polydata, as read by ParaView.
+    Vtk,
+    PlyAscii,
+    PlyBinary,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Vtk => "vtk",
+            ExportFormat::PlyAscii | ExportFormat::PlyBinary => "ply",
+        }
+    }
+}
+
+/// Writes the particles with their velocity, mass, phase, density, color and custom
+/// channels.
+pub fn write_particles(
+    format: ExportFormat,
+    particles: &[Particle],
+    channels: &Channels,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(writer);
+    match format {
+        ExportFormat::Csv => write_csv(particles, channels, &mut writer),
+        ExportFormat::Vtk => write_vtk(particles, channels, &mut writer),
+        ExportFormat::PlyAscii => write_ply(particles, channels, false, &mut writer),
+        ExportFormat::PlyBinary => write_ply(particles, channels, true, &mut writer),
+    }?;
+    writer.flush()
+}
+
+type Attribute = Box<dyn Fn(&Particle) -> f64>;
+
+/// Scalar attributes besides the position, velocity and color.
+fn scalars(channels: &Channels) -> Vec<(String, Attribute)> {
+    let mut scalars: Vec<(String, Attribute)> = vec![
+        ("mass".to_string(), Box::new(|p: &Particle| p.mass)),
+        ("phase".to_string(), Box::new(|p: &Particle| p.phase as f64)),
+        (
+            "density".to_string(),
+            Box::new(|p: &Particle| p.sph.number_density),
+        ),
+    ];
+    channels.names().iter().enumerate().for_each(|(i, name)| {
+        let value = move |p: &Particle| p.channels.get(i).copied().unwrap_or(0.);
+        scalars.push((name.clone(), Box::new(value)));
+    });
+    scalars
+}
+
+/// VTK and PLY names can't contain whitespace.
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect()
+}
+
+fn csv_field(name: &str) -> String {
+    if name.contains([',', '"', '\n']) {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    } else {
+        name.to_string()
+    }
+}
+
+fn write_csv(particles: &[Particle], channels: &Channels, w: &mut impl Write) -> io::Result<()> {
+    let scalars = scalars(channels);
+    let mut header = ["x", "y", "vx", "vy", "r", "g", "b", "a"]
+        .map(String::from)
+        .to_vec();
+    header.extend(scalars.iter().map(|(name, _)| csv_field(name)));
+    writeln!(w, "{}", header.join(","))?;
+    for p in particles {
+        let Particle {
+            position,
+            velocity,
+            color,
+            ..
+        } = p;
+        write!(
+            w,
+            "{},{},{},{},{},{},{},{}",
+            position.x, position.y, velocity.x, velocity.y, color.r, color.g, color.b, color.a
+        )?;
+        for (_, value) in &scalars {
+            write!(w, ",{}", value(p))?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+fn write_vtk(particles: &[Particle], channels: &Channels, w: &mut impl Write) -> io::Result<()> {
+    let n = particles.len();
+    writeln!(w, "# vtk DataFile Version 3.0")?;
+    writeln!(w, "fluid particles")?;
+    writeln!(w, "ASCII")?;
+    writeln!(w, "DATASET POLYDATA")?;
+    writeln!(w, "POINTS {n} double")?;
+    for p in particles {
+        writeln!(w, "{} {} 0", p.position.x, p.position.y)?;
+    }
+    writeln!(w, "VERTICES {n} {}", 2 * n)?;
+    for i in 0..n {
+        writeln!(w, "1 {i}")?;
+    }
+    writeln!(w, "POINT_DATA {n}")?;
+    writeln!(w, "VECTORS velocity double")?;
+    for p in particles {
+        writeln!(w, "{} {} 0", p.velocity.x, p.velocity.y)?;
+    }
+    writeln!(w, "COLOR_SCALARS color 4")?;
+    for p in particles {
+        let c = p.color;
+        let [r, g, b, a] = [c.r, c.g, c.b, c.a].map(|v| v as f64 / 255.);
+        writeln!(w, "{r} {g} {b} {a}")?;
+    }
+    for (name, value) in scalars(channels) {
+        writeln!(w, "SCALARS {} double 1", identifier(&name))?;
+        writeln!(w, "LOOKUP_TABLE default")?;
+        for p in particles {
+            writeln!(w, "{}", value(p))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_ply(
+    particles: &[Particle],
+    channels: &Channels,
+    binary: bool,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let scalars = scalars(channels);
+    let format = if binary {
+        "binary_little_endian"
+    } else {
+        "ascii"
+    };
+    writeln!(w, "ply")?;
+    writeln!(w, "format {format} 1.0")?;
+    writeln!(w, "comment fluid particles")?;
+    writeln!(w, "element vertex {}", particles.len())?;
+    for name in ["x", "y", "z", "vx", "vy"] {
+        writeln!(w, "property double {name}")?;
+    }
+    for name in ["red", "green", "blue", "alpha"] {
+        writeln!(w, "property uchar {name}")?;
+    }
+    for (name, _) in &scalars {
+        writeln!(w, "property double {}", identifier(name))?;
+    }
+    writeln!(w, "end_header")?;
+    for p in particles {
+        let vector = [p.position.x, p.position.y, 0., p.velocity.x, p.velocity.y];
+        let color = [p.color.r, p.color.g, p.color.b, p.color.a];
+        let values = scalars.iter().map(|(_, value)| value(p));
+        if binary {
+            vector
+                .into_iter()
+                .try_for_each(|v| w.write_all(&v.to_le_bytes()))?;
+            w.write_all(&color)?;
+            values
+                .map(f64::to_le_bytes)
+                .try_for_each(|v| w.write_all(&v))?;
+        } else {
+            let vector = vector.map(|v| v.to_string()).join(" ");
+            let color = color.map(|v| v.to_string()).join(" ");
+            let values = values.map(|v| format!(" {v}")).collect::<String>();
+            writeln!(w, "{vector} {color}{values}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::v2::V2;
+    use super::*;
+
+    #[test]
+    fn formats_list_every_particle_and_channel() {
+        let mut channels = Channels::default();
+        channels.register("dye");
+        let mut particle = Particle::new(V2::new(1., 2.), V2::new(3., 4.));
+        particle.channels = vec![0.5];
+        let particles = vec![particle.clone(), particle];
+        let export = |format| {
+            let mut bytes = Vec::new();
+            write_particles(format, &particles, &channels, &mut bytes).unwrap();
+            bytes
+        };
+
+        let csv = String::from_utf8(export(ExportFormat::Csv)).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.starts_with("x,y,vx,vy,r,g,b,a,mass,phase,density,dye\n1,2,3,4,"));
+        assert!(csv.lines().nth(1).unwrap().ends_with(",0.5"));
+
+        let vtk = String::from_utf8(export(ExportFormat::Vtk)).unwrap();
+        assert!(vtk.contains("POINTS 2 double\n1 2 0\n1 2 0\n"));
+        assert!(vtk.contains("SCALARS dye double 1\nLOOKUP_TABLE default\n0.5\n0.5\n"));
+
+        let ascii = export(ExportFormat::PlyAscii);
+        let binary = export(ExportFormat::PlyBinary);
+        let header_end = |bytes: &[u8]| {
+            let end = b"end_header\n";
+            bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len()
+        };
+        // 9 doubles and 4 bytes per particle
+        assert_eq!(binary.len() - header_end(&binary), 2 * (9 * 8 + 4));
+        let body = String::from_utf8(ascii[header_end(&ascii)..].to_vec()).unwrap();
+        assert_eq!(
+            body.lines().next(),
+            Some("1 2 0 3 4 255 255 255 255 1 0 0 0.5")
+        );
+    }
+}
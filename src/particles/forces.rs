@@ -0,0 +1,258 @@
+use js_sys::{Array, Function};
+use wasm_bindgen::JsValue;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{finite, non_negative, positive, FluidError};
+
+use super::{particle::Particle, v2::V2};
+
+/// An acceleration field acting on the fluid, evaluated for every particle each step.
+///
+/// Any `Fn(&Particle, f64) -> V2` closure is a force field.
+pub trait ForceField {
+    /// Acceleration of `particle` at simulation time `time`.
+    fn acceleration(&self, particle: &Particle, time: f64) -> V2;
+
+    /// Description of the field for snapshots and scenes, `None` for fields that can't
+    /// be saved, like closures.
+    fn spec(&self) -> Option<FieldSpec> {
+        None
+    }
+}
+
+/// The built-in fields, which snapshots and scenes can hold.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldSpec {
+    Uniform(UniformField),
+    Radial(RadialField),
+    Vortex(VortexField),
+    Drag(DragField),
+    Noise(NoiseField),
+}
+
+impl FieldSpec {
+    pub fn into_field(self) -> Box<dyn ForceField> {
+        match self {
+            FieldSpec::Uniform(field) => Box::new(field),
+            FieldSpec::Radial(field) => Box::new(field),
+            FieldSpec::Vortex(field) => Box::new(field),
+            FieldSpec::Drag(field) => Box::new(field),
+            FieldSpec::Noise(field) => Box::new(field),
+        }
+    }
+
+    /// Checks the same ranges as the `CanvasDriven` methods adding these fields.
+    pub(crate) fn validate(&self) -> Result<(), FluidError> {
+        match self {
+            FieldSpec::Uniform(field) => finite(
+                "acceleration",
+                &[field.acceleration.x, field.acceleration.y],
+            ),
+            FieldSpec::Radial(RadialField {
+                center,
+                radius,
+                strength,
+                falloff,
+            })
+            | FieldSpec::Vortex(VortexField {
+                center,
+                radius,
+                strength,
+                falloff,
+            }) => {
+                finite("center", &[center.x, center.y])?;
+                positive("radius", *radius)?;
+                finite("strength", &[*strength])?;
+                non_negative("falloff", *falloff)
+            }
+            FieldSpec::Drag(field) => non_negative("coefficient", field.coefficient),
+            FieldSpec::Noise(field) => {
+                finite("strength", &[field.strength])?;
+                positive("scale", field.scale)?;
+                finite("speed", &[field.speed])
+            }
+        }
+    }
+}
+
+impl<F: Fn(&Particle, f64) -> V2> ForceField for F {
+    fn acceleration(&self, particle: &Particle, time: f64) -> V2 {
+        self(particle, time)
+    }
+}
+
+/// The same acceleration everywhere, like wind.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UniformField {
+    pub acceleration: V2,
+}
+
+impl ForceField for UniformField {
+    fn acceleration(&self, _particle: &Particle, _time: f64) -> V2 {
+        self.acceleration
+    }
+
+    fn spec(&self) -> Option<FieldSpec> {
+        Some(FieldSpec::Uniform(self.clone()))
+    }
+}
+
+/// Strength of a field that fades out towards its radius, `falloff` is the exponent
+/// of `1 - d / radius`, 0 keeps the full strength up to the radius.
+fn falloff(strength: f64, falloff: f64, d: f64, radius: f64) -> f64 {
+    strength * (1. - d / radius).max(0.).powf(falloff)
+}
+
+/// Pulls particles towards `center`, a negative strength pushes them away.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RadialField {
+    pub center: V2,
+    pub radius: f64,
+    pub strength: f64,
+    pub falloff: f64,
+}
+
+impl ForceField for RadialField {
+    fn acceleration(&self, particle: &Particle, _time: f64) -> V2 {
+        let offset = self.center.sub(&particle.position);
+        let d = offset.len();
+        if d < 0.001 || d > self.radius {
+            return V2::new(0., 0.);
+        }
+        falloff(self.strength, self.falloff, d, self.radius) * offset.normalized()
+    }
+
+    fn spec(&self) -> Option<FieldSpec> {
+        Some(FieldSpec::Radial(self.clone()))
+    }
+}
+
+/// Swirls particles around `center`, clockwise on screen for a positive strength.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VortexField {
+    pub center: V2,
+    pub radius: f64,
+    pub strength: f64,
+    pub falloff: f64,
+}
+
+impl ForceField for VortexField {
+    fn acceleration(&self, particle: &Particle, _time: f64) -> V2 {
+        let offset = particle.position.sub(&self.center);
+        let d = offset.len();
+        if d < 0.001 || d > self.radius {
+            return V2::new(0., 0.);
+        }
+        let tangent = V2::new(-offset.y, offset.x).normalized();
+        falloff(self.strength, self.falloff, d, self.radius) * tangent
+    }
+
+    fn spec(&self) -> Option<FieldSpec> {
+        Some(FieldSpec::Vortex(self.clone()))
+    }
+}
+
+/// Slows particles down in proportion to their velocity.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DragField {
+    pub coefficient: f64,
+}
+
+impl ForceField for DragField {
+    fn acceleration(&self, particle: &Particle, _time: f64) -> V2 {
+        -self.coefficient * particle.velocity
+    }
+
+    fn spec(&self) -> Option<FieldSpec> {
+        Some(FieldSpec::Drag(self.clone()))
+    }
+}
+
+/// Divergence free turbulence, the curl of a value noise of feature size `scale`
+/// scrolling by `speed` noise cells per second.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoiseField {
+    pub strength: f64,
+    pub scale: f64,
+    pub speed: f64,
+}
+
+impl ForceField for NoiseField {
+    fn acceleration(&self, particle: &Particle, time: f64) -> V2 {
+        let x = particle.position.x / self.scale;
+        let y = particle.position.y / self.scale + time * self.speed;
+        let e = 0.01;
+        let dx = (value_noise(x + e, y) - value_noise(x - e, y)) / (2. * e);
+        let dy = (value_noise(x, y + e) - value_noise(x, y - e)) / (2. * e);
+        self.strength * V2::new(dy, -dx)
+    }
+
+    fn spec(&self) -> Option<FieldSpec> {
+        Some(FieldSpec::Noise(self.clone()))
+    }
+}
+
+fn lattice_hash(i: i64, j: i64) -> f64 {
+    let mut h = (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (j as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h ^= h >> 29;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 32;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Smoothly interpolated random values on the integer lattice, in [0, 1].
+fn value_noise(x: f64, y: f64) -> f64 {
+    let (i, j) = (x.floor(), y.floor());
+    let smooth = |t: f64| t * t * (3. - 2. * t);
+    let (fx, fy) = (smooth(x - i), smooth(y - j));
+    let (i, j) = (i as i64, j as i64);
+    let top = lattice_hash(i, j) * (1. - fx) + lattice_hash(i + 1, j) * fx;
+    let bottom = lattice_hash(i, j + 1) * (1. - fx) + lattice_hash(i + 1, j + 1) * fx;
+    top * (1. - fy) + bottom * fy
+}
+
+/// A field computed by a JS function `(x, y, vx, vy, time) => [ax, ay]`.
+pub struct JsFunctionField {
+    pub function: Function,
+}
+
+impl ForceField for JsFunctionField {
+    fn acceleration(&self, particle: &Particle, time: f64) -> V2 {
+        let args = Array::of5(
+            &particle.position.x.into(),
+            &particle.position.y.into(),
+            &particle.velocity.x.into(),
+            &particle.velocity.y.into(),
+            &time.into(),
+        );
+        let Ok(result) = self.function.apply(&JsValue::NULL, &args) else {
+            return V2::new(0., 0.);
+        };
+        let result = Array::from(&result);
+        let ax = result.get(0).as_f64().unwrap_or(0.);
+        let ay = result.get(1).as_f64().unwrap_or(0.);
+        V2::new(ax, ay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radial_field_fades_out_to_its_radius() {
+        let field = RadialField {
+            center: V2::new(0., 0.),
+            radius: 10.,
+            strength: 4.,
+            falloff: 1.,
+        };
+        let near = field.acceleration(&Particle::new(V2::new(5., 0.), V2::new(0., 0.)), 0.);
+        assert_eq!(near, V2::new(-2., 0.));
+        let far = field.acceleration(&Particle::new(V2::new(0., 11.), V2::new(0., 0.)), 0.);
+        assert_eq!(far, V2::new(0., 0.));
+    }
+}
@@ -0,0 +1,358 @@
+use wasm_bindgen::prelude::*;
+use web_sys::CanvasRenderingContext2d;
+
+use super::{tree_drawings::Painter, v2::V2};
+use crate::error::FluidError;
+use crate::snapshot::{Decode, Encode, Reader, SnapshotError, SnapshotKind, Writer};
+
+/// A drawing call, with single precision coordinates to keep packets small.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Command {
+    Save,
+    Restore,
+    FillStyle(String),
+    StrokeStyle(String),
+    BeginPath,
+    MoveTo([f32; 2]),
+    LineTo([f32; 2]),
+    QuadraticCurveTo([f32; 4]),
+    BezierCurveTo([f32; 6]),
+    ClosePath,
+    Rect([f32; 4]),
+    Arc([f32; 3]),
+    /// `x, y` pairs of the centers.
+    Squares {
+        half_size: f32,
+        centers: Vec<f32>,
+    },
+    Fill,
+    Stroke,
+    FillText {
+        text: String,
+        x: f32,
+        y: f32,
+    },
+}
+
+/// Everything needed to draw a frame of the simulation, the particles and the overlays,
+/// recorded where the simulation runs, e.g. a worker, and drawn by a `FrameRenderer`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct FramePacket {
+    pub width: f64,
+    pub height: f64,
+    pub commands: Vec<Command>,
+}
+
+impl FramePacket {
+    pub fn new(width: f64, height: f64) -> FramePacket {
+        FramePacket {
+            width,
+            height,
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(SnapshotKind::Frame);
+        writer.put(&self.width);
+        writer.put(&self.height);
+        writer.put(&self.commands);
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<FramePacket, SnapshotError> {
+        let mut reader = Reader::new(bytes, SnapshotKind::Frame)?;
+        let packet = FramePacket {
+            width: reader.get()?,
+            height: reader.get()?,
+            commands: reader.get()?,
+        };
+        reader.finish()?;
+        Ok(packet)
+    }
+
+    /// Draws the recorded commands.
+    pub fn replay(&self, painter: &mut dyn Painter) -> Result<(), FluidError> {
+        let f = |v: f32| v as f64;
+        for command in &self.commands {
+            match command {
+                Command::Save => painter.save(),
+                Command::Restore => painter.restore(),
+                Command::FillStyle(color) => painter.set_fill_style(color),
+                Command::StrokeStyle(color) => painter.set_stroke_style(color),
+                Command::BeginPath => painter.begin_path(),
+                Command::MoveTo([x, y]) => painter.move_to(f(*x), f(*y)),
+                Command::LineTo([x, y]) => painter.line_to(f(*x), f(*y)),
+                Command::QuadraticCurveTo([cx, cy, x, y]) => {
+                    painter.quadratic_curve_to(f(*cx), f(*cy), f(*x), f(*y))
+                }
+                Command::BezierCurveTo([c1x, c1y, c2x, c2y, x, y]) => {
+                    painter.bezier_curve_to(f(*c1x), f(*c1y), f(*c2x), f(*c2y), f(*x), f(*y))
+                }
+                Command::ClosePath => painter.close_path(),
+                Command::Rect([x, y, width, height]) => {
+                    painter.rect(f(*x), f(*y), f(*width), f(*height))
+                }
+                Command::Arc([x, y, radius]) => painter.arc(f(*x), f(*y), f(*radius))?,
+                Command::Squares { half_size, centers } => {
+                    let centers: Vec<V2> = centers
+                        .chunks_exact(2)
+                        .map(|c| V2::new(f(c[0]), f(c[1])))
+                        .collect();
+                    painter.squares(&centers, f(*half_size));
+                }
+                Command::Fill => painter.fill(),
+                Command::Stroke => painter.stroke(),
+                Command::FillText { text, x, y } => painter.fill_text(text, f(*x), f(*y))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Painter for FramePacket {
+    fn save(&mut self) {
+        self.commands.push(Command::Save);
+    }
+
+    fn restore(&mut self) {
+        self.commands.push(Command::Restore);
+    }
+
+    fn set_fill_style(&mut self, color: &str) {
+        self.commands.push(Command::FillStyle(color.to_string()));
+    }
+
+    fn set_stroke_style(&mut self, color: &str) {
+        self.commands.push(Command::StrokeStyle(color.to_string()));
+    }
+
+    fn begin_path(&mut self) {
+        self.commands.push(Command::BeginPath);
+    }
+
+    fn move_to(&mut self, x: f64, y: f64) {
+        self.commands.push(Command::MoveTo([x as f32, y as f32]));
+    }
+
+    fn line_to(&mut self, x: f64, y: f64) {
+        self.commands.push(Command::LineTo([x as f32, y as f32]));
+    }
+
+    fn quadratic_curve_to(&mut self, cx: f64, cy: f64, x: f64, y: f64) {
+        let values = [cx, cy, x, y].map(|v| v as f32);
+        self.commands.push(Command::QuadraticCurveTo(values));
+    }
+
+    fn bezier_curve_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) {
+        let values = [c1x, c1y, c2x, c2y, x, y].map(|v| v as f32);
+        self.commands.push(Command::BezierCurveTo(values));
+    }
+
+    fn close_path(&mut self) {
+        self.commands.push(Command::ClosePath);
+    }
+
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        let values = [x, y, width, height].map(|v| v as f32);
+        self.commands.push(Command::Rect(values));
+    }
+
+    fn arc(&mut self, x: f64, y: f64, radius: f64) -> Result<(), FluidError> {
+        let values = [x, y, radius].map(|v| v as f32);
+        self.commands.push(Command::Arc(values));
+        Ok(())
+    }
+
+    fn squares(&mut self, centers: &[V2], half_size: f64) {
+        let centers = centers
+            .iter()
+            .flat_map(|c| [c.x as f32, c.y as f32])
+            .collect();
+        self.commands.push(Command::Squares {
+            half_size: half_size as f32,
+            centers,
+        });
+    }
+
+    fn fill(&mut self) {
+        self.commands.push(Command::Fill);
+    }
+
+    fn stroke(&mut self) {
+        self.commands.push(Command::Stroke);
+    }
+
+    fn fill_text(&mut self, text: &str, x: f64, y: f64) -> Result<(), FluidError> {
+        self.commands.push(Command::FillText {
+            text: text.to_string(),
+            x: x as f32,
+            y: y as f32,
+        });
+        Ok(())
+    }
+}
+
+fn put_values(writer: &mut Writer, values: &[f32]) {
+    values.iter().for_each(|value| writer.put(value));
+}
+
+fn get_values<const N: usize>(reader: &mut Reader) -> Result<[f32; N], SnapshotError> {
+    let mut values = [0.; N];
+    for value in values.iter_mut() {
+        *value = reader.get()?;
+    }
+    Ok(values)
+}
+
+impl Encode for Command {
+    fn encode(&self, writer: &mut Writer) {
+        let tag: u8 = match self {
+            Command::Save => 0,
+            Command::Restore => 1,
+            Command::FillStyle(_) => 2,
+            Command::StrokeStyle(_) => 3,
+            Command::BeginPath => 4,
+            Command::MoveTo(_) => 5,
+            Command::LineTo(_) => 6,
+            Command::QuadraticCurveTo(_) => 7,
+            Command::BezierCurveTo(_) => 8,
+            Command::ClosePath => 9,
+            Command::Rect(_) => 10,
+            Command::Arc(_) => 11,
+            Command::Squares { .. } => 12,
+            Command::Fill => 13,
+            Command::Stroke => 14,
+            Command::FillText { .. } => 15,
+        };
+        writer.put(&tag);
+        match self {
+            Command::FillStyle(color) | Command::StrokeStyle(color) => writer.put(color),
+            Command::MoveTo(values) | Command::LineTo(values) => put_values(writer, values),
+            Command::QuadraticCurveTo(values) | Command::Rect(values) => put_values(writer, values),
+            Command::BezierCurveTo(values) => put_values(writer, values),
+            Command::Arc(values) => put_values(writer, values),
+            Command::Squares { half_size, centers } => {
+                writer.put(half_size);
+                writer.put(centers);
+            }
+            Command::FillText { text, x, y } => {
+                writer.put(text);
+                writer.put(x);
+                writer.put(y);
+            }
+            Command::Save
+            | Command::Restore
+            | Command::BeginPath
+            | Command::ClosePath
+            | Command::Fill
+            | Command::Stroke => {}
+        }
+    }
+}
+
+impl Decode for Command {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(match reader.get::<u8>()? {
+            0 => Command::Save,
+            1 => Command::Restore,
+            2 => Command::FillStyle(reader.get()?),
+            3 => Command::StrokeStyle(reader.get()?),
+            4 => Command::BeginPath,
+            5 => Command::MoveTo(get_values(reader)?),
+            6 => Command::LineTo(get_values(reader)?),
+            7 => Command::QuadraticCurveTo(get_values(reader)?),
+            8 => Command::BezierCurveTo(get_values(reader)?),
+            9 => Command::ClosePath,
+            10 => Command::Rect(get_values(reader)?),
+            11 => Command::Arc(get_values(reader)?),
+            12 => Command::Squares {
+                half_size: reader.get()?,
+                centers: reader.get()?,
+            },
+            13 => Command::Fill,
+            14 => Command::Stroke,
+            15 => Command::FillText {
+                text: reader.get()?,
+                x: reader.get()?,
+                y: reader.get()?,
+            },
+            _ => return Err(SnapshotError::Invalid("draw command")),
+        })
+    }
+}
+
+/// Draws the frame packets of a `CanvasDriven` running elsewhere, e.g. in a worker, so
+/// that stepping the simulation doesn't block the thread that renders.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct FrameRenderer {
+    packet: FramePacket,
+}
+
+#[wasm_bindgen]
+impl FrameRenderer {
+    pub fn new() -> FrameRenderer {
+        FrameRenderer::default()
+    }
+
+    /// Takes the packet of `CanvasDriven::frame_packet` to draw next.
+    pub fn load(&mut self, packet: &[u8]) -> Result<(), FluidError> {
+        self.packet = FramePacket::from_bytes(packet)?;
+        Ok(())
+    }
+
+    pub fn width(&self) -> f64 {
+        self.packet.width
+    }
+
+    pub fn height(&self) -> f64 {
+        self.packet.height
+    }
+
+    pub fn draw(&self, ctx: JsValue) -> Result<(), FluidError> {
+        let mut ctx: CanvasRenderingContext2d =
+            ctx.dyn_into().map_err(|_| FluidError::NotACanvasContext)?;
+        self.packet.replay(&mut ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{CanvasDriven, CanvasDrivenArgs};
+    use super::*;
+
+    #[test]
+    fn packets_round_trip() {
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            particles: 200,
+            seed: Some(2),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        canvas.update_mouse_pos(200., 200., true).unwrap();
+        canvas.evolve(3).unwrap();
+        let bytes = canvas.frame_packet().unwrap();
+        let packet = FramePacket::from_bytes(&bytes).unwrap();
+        assert_eq!(packet.to_bytes(), bytes);
+        let particles: usize = packet
+            .commands
+            .iter()
+            .map(|command| match command {
+                Command::Squares { centers, .. } => centers.len() / 2,
+                _ => 0,
+            })
+            .sum();
+        // the particles near the pressed mouse are highlighted a second time
+        assert!(particles > 200);
+        assert!(packet.commands.iter().any(|c| matches!(c, Command::Arc(_))));
+
+        let mut replayed = FramePacket::new(packet.width, packet.height);
+        packet.replay(&mut replayed).unwrap();
+        assert_eq!(replayed, packet);
+        assert_eq!(
+            FramePacket::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::UnexpectedEnd)
+        );
+    }
+}
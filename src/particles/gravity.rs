@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use super::{particle::PARTICLE_RADIUS, v2::V2};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Gravity {
+    Uniform(V2),
+    /// Pulls everything towards `center` with a constant `strength`.
+    Point {
+        center: V2,
+        strength: f64,
+    },
+    Zero,
+}
+
+impl Default for Gravity {
+    fn default() -> Gravity {
+        Gravity::Uniform(V2::new(0., 30.))
+    }
+}
+
+impl Gravity {
+    /// Uniform gravity of `magnitude` seen by a device tilted by the `beta` (front to
+    /// back) and `gamma` (left to right) angles of a `deviceorientation` event, in degrees.
+    pub fn from_orientation(beta: f64, gamma: f64, magnitude: f64) -> Gravity {
+        let (beta, gamma) = (beta.to_radians(), gamma.to_radians());
+        Gravity::Uniform(magnitude * V2::new(beta.cos() * gamma.sin(), beta.sin()))
+    }
+
+    pub fn acceleration(&self, point: &V2) -> V2 {
+        match self {
+            Gravity::Uniform(acceleration) => *acceleration,
+            Gravity::Point { center, strength } => {
+                // fades out linearly close to the center so particles don't jitter over it
+                let offset = center.sub(point);
+                (strength / offset.len().max(PARTICLE_RADIUS)) * offset
+            }
+            Gravity::Zero => V2::new(0., 0.),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_and_orientation_gravity() {
+        let point = Gravity::Point {
+            center: V2::new(100., 100.),
+            strength: 30.,
+        };
+        assert_eq!(point.acceleration(&V2::new(0., 100.)), V2::new(30., 0.));
+        assert_eq!(point.acceleration(&V2::new(100., 200.)), V2::new(0., -30.));
+        let near = point.acceleration(&V2::new(101., 100.));
+        assert!((near.x + 30. / PARTICLE_RADIUS).abs() < 1e-9);
+        assert_eq!(point.acceleration(&V2::new(100., 100.)), V2::new(0., 0.));
+
+        // flat on a table the screen sees no gravity, upright it points down
+        let flat = Gravity::from_orientation(0., 0., 30.).acceleration(&V2::new(0., 0.));
+        assert!(flat.len() < 1e-9);
+        let upright = Gravity::from_orientation(90., 0., 30.).acceleration(&V2::new(0., 0.));
+        assert!(upright.distance_to(&V2::new(0., 30.)) < 1e-9);
+        let tilted = Gravity::from_orientation(0., 90., 30.).acceleration(&V2::new(0., 0.));
+        assert!(tilted.distance_to(&V2::new(30., 0.)) < 1e-9);
+    }
+}
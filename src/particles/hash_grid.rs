@@ -36,16 +36,14 @@ impl BuildHasher for FastHasherBuilder {
 }
 
 pub struct HashGrid<T> {
-    max_dim: f64,
     divisor: f64,
     data: HashMap<(i32, i32), Vec<T>, FastHasherBuilder>,
 }
 
 impl<T: TreeValue> HashGrid<T> {
-    fn _from_vec(vec: Vec<T>, max_dim: f64) -> Self {
+    fn _from_vec(vec: Vec<T>) -> Self {
         let divisor = 10.;
         let mut grid = HashGrid {
-            max_dim,
             data: HashMap::with_hasher(FastHasherBuilder {}),
             divisor,
         };
@@ -64,7 +62,7 @@ impl<T: TreeValue> HashGrid<T> {
 
     pub fn get_rects(&self) -> Vec<Rect> {
         let mut rects = vec![];
-        for (key, _) in &self.data {
+        for key in self.data.keys() {
             let x = key.0 as f64 * self.divisor;
             let y = key.1 as f64 * self.divisor;
             let width = self.divisor;
@@ -75,7 +73,7 @@ impl<T: TreeValue> HashGrid<T> {
     }
 
     fn neighbor_keys(&self, key: &(i32, i32)) -> impl Iterator<Item = (i32, i32)> {
-        let (x, y) = key.clone();
+        let (x, y) = *key;
         (0..9).map(move |i| {
             let x = x + i / 3 - 1;
             let y = y + i % 3 - 1;
@@ -85,8 +83,8 @@ impl<T: TreeValue> HashGrid<T> {
 }
 
 impl<T: TreeValue> GeoQuery<T> for HashGrid<T> {
-    fn from_vec(vec: Vec<T>, max_dim: f64) -> Self {
-        Self::_from_vec(vec, max_dim)
+    fn from_vec(vec: Vec<T>, _max_dim: f64) -> Self {
+        Self::_from_vec(vec)
     }
 
     #[inline(never)]
@@ -34,10 +34,6 @@ impl<S: SpaceFillingCurve> SpaceFillingTree<S> {
         tree
     }
 
-    pub fn values<'a>(&'a self) -> impl Iterator<Item = &'a S::T> {
-        self.values.iter().map(|v| &v.value)
-    }
-
     pub fn get_rect_limits(&self, rect: &Rect) -> (u64, u64) {
         let top_left = S::number_of(rect.x0, rect.y0);
         let top_right = S::number_of(rect.x1, rect.y0);
@@ -78,7 +74,7 @@ impl<S: SpaceFillingCurve> SpaceFillingTree<S> {
             if value.order < order {
                 return std::cmp::Ordering::Less;
             }
-            return std::cmp::Ordering::Greater;
+            std::cmp::Ordering::Greater
         });
         match r {
             Ok(i) => i,
@@ -94,7 +90,7 @@ impl<S: SpaceFillingCurve> GeoQuery<S::T> for SpaceFillingTree<S> {
         slice.iter().for_each(|value| f(&value.value));
     }
 
-    fn from_vec(vec: Vec<S::T>, max_dim: f64) -> Self {
+    fn from_vec(vec: Vec<S::T>, _max_dim: f64) -> Self {
         SpaceFillingTree::from_vec(vec)
     }
 }
@@ -1,6 +1,14 @@
+use crate::error::{finite, non_negative, positive, FluidError};
+use crate::snapshot::{Reader, SnapshotError, SnapshotKind, Writer};
+use kurbo::{BezPath, Shape};
 use quad_tree::QuadTree;
 use rstar_tree::RStartree;
-use tree_drawings::{DrawContext, Drawable};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+};
+use tree_drawings::{DrawContext, Drawable, Painter};
 use wasm_bindgen::prelude::*;
 use web_sys::CanvasRenderingContext2d;
 mod hilbert_tree;
@@ -17,15 +25,70 @@ use particle::*;
 use v2::V2;
 mod hash_grid;
 use hash_grid::HashGrid;
+mod attributes;
+use attributes::{Channels, Rgba};
+mod boundary;
+mod buffers;
+use buffers::ParticleBuffers;
+mod dfsph;
+mod emitters;
+pub use emitters::EmitterParams;
+use emitters::{Emitter, EmitterShape, Sink};
+mod export;
+pub use export::ExportFormat;
+mod forces;
+mod frame;
+use frame::FramePacket;
+pub use frame::FrameRenderer;
+mod gravity;
+use forces::{
+    DragField, ForceField, JsFunctionField, NoiseField, RadialField, UniformField, VortexField,
+};
+use gravity::Gravity;
+mod neighbours;
+mod obstacles;
+mod params;
+mod pbf;
+use obstacles::{Obstacle, ObstacleShape};
+mod phases;
+use phases::Phase;
+mod recorder;
+use recorder::{Event, Input, Recording};
+mod rewind;
+use rewind::History;
+mod rng;
+mod scene;
+mod scheduler;
+pub(crate) use scene::Scene;
+pub use scene::SceneError;
+use scheduler::Scheduler;
+mod sdf;
+mod snapshot;
+pub use sdf::SdfOp;
+use sdf::SdfShape;
+mod sph;
+mod tools;
+mod walls;
+pub use params::{SimulationParams, Solver, SolverStats, WallMode, WallParams};
+pub use tools::Tool;
+use tools::{Paint, PointerState};
 
 #[wasm_bindgen]
 pub struct CanvasDriven {
     world: Box<dyn ParticleWorld>,
     draw_context: DrawContext,
+    tree_type: TreeType,
+    recording: Option<Recording>,
+    /// Recorded events still to be played back.
+    replay: VecDeque<Event>,
+    history: Option<History>,
+    buffers: ParticleBuffers,
+    scheduler: Scheduler,
 }
 
 #[wasm_bindgen]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TreeType {
     ZOrder,
     Hilbert,
@@ -40,20 +103,34 @@ pub struct CanvasDrivenArgs {
     pub height: f64,
     pub particles: usize,
     pub tree_type: TreeType,
+    pub params: SimulationParams,
+    /// Seed of the world's random numbers, a random seed when unset.
+    pub seed: Option<u64>,
 }
 
 #[wasm_bindgen]
 impl CanvasDrivenArgs {
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Self {
         CanvasDrivenArgs {
             width: 800.,
             height: 600.,
             particles: 100,
             tree_type: TreeType::RStar,
+            params: SimulationParams::default(),
+            seed: None,
         }
     }
 }
 
+impl CanvasDrivenArgs {
+    fn validate(&self) -> Result<(), FluidError> {
+        positive("width", self.width)?;
+        positive("height", self.height)?;
+        self.params.validate()
+    }
+}
+
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = Math)]
@@ -62,8 +139,9 @@ extern "C" {
 
 #[wasm_bindgen]
 impl CanvasDriven {
-    pub fn new(args: CanvasDrivenArgs) -> CanvasDriven {
-        match args.tree_type {
+    pub fn new(args: CanvasDrivenArgs) -> Result<CanvasDriven, FluidError> {
+        args.validate()?;
+        let canvas = match args.tree_type {
             TreeType::Hilbert => {
                 CanvasDriven::_new::<SpaceFillingTree<HilbertCurve<Particle>>>(args)
             }
@@ -71,7 +149,8 @@ impl CanvasDriven {
             TreeType::Quad => CanvasDriven::_new::<QuadTree<Particle>>(args),
             TreeType::RStar => CanvasDriven::_new::<RStartree<Particle>>(args),
             TreeType::HashGrid => CanvasDriven::_new::<HashGrid<Particle>>(args),
-        }
+        };
+        Ok(canvas)
     }
 
     fn _new<T: GeoQuery<Particle> + Drawable + 'static>(args: CanvasDrivenArgs) -> CanvasDriven {
@@ -79,70 +158,1468 @@ impl CanvasDriven {
             width,
             height,
             particles,
-            ..
+            tree_type,
+            params,
+            seed,
         } = args;
-        let gravity = V2::new(0., 30.);
-        let mut world = World::<T>::new(V2::new(width, height), gravity);
-        world.add_random_particles(particles, random);
+        let gravity = Gravity::default();
+        let seed = seed.unwrap_or_else(|| (random() * u64::MAX as f64) as u64);
+        let mut world = World::<T>::new(V2::new(width, height), gravity, seed);
+        world.params = params;
+        world.add_random_particles(particles);
+        let draw_context = DrawContext {
+            mouse_pos: None,
+            mouse_radius: 50.,
+            width,
+            height,
+        };
+        world.set_tool(Tool::Attract, draw_context.mouse_radius);
         CanvasDriven {
             world: Box::new(world),
-            draw_context: DrawContext {
-                mouse_pos: None,
-                mouse_radius: 50.,
-                width,
-                height,
-            },
+            draw_context,
+            tree_type,
+            recording: None,
+            replay: VecDeque::new(),
+            history: None,
+            buffers: ParticleBuffers::default(),
+            scheduler: Scheduler::default(),
+        }
+    }
+
+    /// Moves the simulation to another spatial index, keeping everything else as it is.
+    pub fn set_tree_type(&mut self, tree_type: TreeType) {
+        // an empty world holds the place while the current one is moved
+        let placeholder = Box::new(World::<HashGrid<Particle>>::new(
+            V2::new(0., 0.),
+            Gravity::Zero,
+            0,
+        ));
+        let world = std::mem::replace(&mut self.world, placeholder);
+        self.world = world.migrate(tree_type);
+        self.tree_type = tree_type;
+    }
+
+    pub fn tree_type(&self) -> TreeType {
+        self.tree_type
+    }
+
+    /// Builds a simulation from a JSON scene, see `scene.rs` for the format.
+    pub fn from_scene(scene: &str) -> Result<CanvasDriven, FluidError> {
+        CanvasDriven::build(&Scene::parse(scene)?)
+    }
+
+    /// The current state as a JSON scene, with every particle listed.
+    pub fn to_scene(&self) -> String {
+        self.world.to_scene(self.tree_type).to_json()
+    }
+
+    /// Saves the whole simulation, to continue it later with `load_snapshot`.
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        let mut writer = Writer::new(SnapshotKind::Fluid);
+        writer.put(&self.tree_type);
+        self.world.encode(&mut writer);
+        writer.finish()
+    }
+
+    /// Replaces the simulation with one saved by `save_snapshot`, including its tree type.
+    pub fn load_snapshot(&mut self, bytes: &[u8]) -> Result<(), FluidError> {
+        Ok(self.restore(bytes)?)
+    }
+
+    /// Starts capturing the mouse and `evolve` calls, from a snapshot of the current state
+    /// and the pointers pressed right now. Other changes made while recording, like a new
+    /// tool, are not part of the recording.
+    pub fn start_recording(&mut self) {
+        let pointers = self.world.pointer_state();
+        self.recording = Some(Recording::new(self.save_snapshot(), pointers));
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Stops recording and returns the session log, to replay with `load_recording`.
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        self.recording.take().map(|recording| recording.to_bytes())
+    }
+
+    /// Restores the state a recording started from and queues its events, which are then
+    /// played back with `replay_until` or `replay_next`.
+    pub fn load_recording(&mut self, bytes: &[u8]) -> Result<(), FluidError> {
+        Ok(self.play(bytes)?)
+    }
+
+    /// Plays back the events recorded up to simulation time `time`, returns the number of
+    /// events left.
+    pub fn replay_until(&mut self, time: f64) -> Result<usize, FluidError> {
+        while self.replay.front().is_some_and(|event| event.time <= time) {
+            self.replay_next()?;
+        }
+        Ok(self.replay.len())
+    }
+
+    /// Plays back the next recorded event, returns the number of events left.
+    pub fn replay_next(&mut self) -> Result<usize, FluidError> {
+        if let Some(event) = self.replay.pop_front() {
+            match event.input {
+                Input::MousePos { x, y, is_pressing } => {
+                    self.update_mouse_pos(x, y, is_pressing)?
+                }
+                Input::RemoveMousePos => self.remove_mouse_pos(),
+                Input::Evolve(n) => self.evolve(n)?,
+            }
+        }
+        Ok(self.replay.len())
+    }
+
+    /// The particles and their attributes in a file format, for download.
+    pub fn export_particles(&self, format: ExportFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        // writing to a vector can't fail
+        let _ = self.write_particles(format, &mut bytes);
+        bytes
+    }
+
+    /// Version of the layout of the particle buffers, to check against the one a
+    /// renderer was written for.
+    pub fn buffer_layout_version(&self) -> u32 {
+        buffers::LAYOUT_VERSION
+    }
+
+    /// Copies the particles into the buffers behind `positions`, `velocities` and
+    /// `attributes`, returns the change counter, which only increases when the copy
+    /// differs from the previous one.
+    pub fn sync_buffers(&mut self) -> u32 {
+        self.buffers
+            .sync(self.world.particles(), self.world.channels())
+    }
+
+    pub fn change_counter(&self) -> u32 {
+        self.buffers.changes
+    }
+
+    /// `x, y` of every particle as of the last `sync_buffers`, as a view into wasm memory.
+    /// The view is invalidated by the next `sync_buffers` and by any growth of the wasm
+    /// memory, so get it again after every sync and don't keep it across calls.
+    pub fn positions(&self) -> js_sys::Float32Array {
+        // SAFETY: the view is only valid until the buffer or the memory is reallocated,
+        // as documented above
+        unsafe { js_sys::Float32Array::view(&self.buffers.positions) }
+    }
+
+    /// `vx, vy` of every particle, a view with the same lifetime as `positions`.
+    pub fn velocities(&self) -> js_sys::Float32Array {
+        // SAFETY: see `positions`
+        unsafe { js_sys::Float32Array::view(&self.buffers.velocities) }
+    }
+
+    /// `attribute_stride` values per particle, named by `attribute_names`: mass, phase,
+    /// density, r, g, b, a and the custom channels. A view with the same lifetime as
+    /// `positions`.
+    pub fn attributes(&self) -> js_sys::Float64Array {
+        // SAFETY: see `positions`
+        unsafe { js_sys::Float64Array::view(&self.buffers.attributes) }
+    }
+
+    pub fn attribute_stride(&self) -> usize {
+        self.buffers.stride
+    }
+
+    pub fn attribute_names(&self) -> Vec<String> {
+        ParticleBuffers::attribute_names(self.world.channels())
+    }
+
+    /// Keeps the states of the last `seconds` of simulation, one per `evolve` call, to move
+    /// back to with `seek`. When they take more than `max_bytes`, the oldest ones are
+    /// dropped a key frame group at a time.
+    pub fn enable_rewind(&mut self, seconds: f64, max_bytes: usize) -> Result<(), FluidError> {
+        positive("seconds", seconds)?;
+        let mut history = History::new(seconds, max_bytes);
+        history.push(self.world.time(), self.save_snapshot());
+        self.history = Some(history);
+        Ok(())
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.history = None;
+    }
+
+    pub fn rewind_frames(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Index of the frame the simulation is at.
+    pub fn rewind_position(&self) -> usize {
+        self.history.as_ref().map_or(0, History::position)
+    }
+
+    pub fn rewind_frame_time(&self, index: usize) -> Option<f64> {
+        self.history.as_ref()?.time(index)
+    }
+
+    /// Bytes used by the rewind frames.
+    pub fn rewind_memory(&self) -> usize {
+        self.history.as_ref().map_or(0, History::memory)
+    }
+
+    /// Moves the simulation back or forward to a frame, returns false if there is no such
+    /// frame. Evolving from a past frame, e.g. after changing the parameters, starts a new
+    /// branch and forgets the frames that followed it.
+    pub fn seek(&mut self, index: usize) -> Result<bool, FluidError> {
+        let Some(snapshot) = self
+            .history
+            .as_mut()
+            .and_then(|history| history.seek(index))
+        else {
+            return Ok(false);
+        };
+        self.restore(&snapshot)?;
+        Ok(true)
+    }
+
+    /// Steps the simulation `n` times. Fails if a step makes the simulation unstable, in
+    /// which case the particles are left as they were before this call.
+    pub fn evolve(&mut self, n: usize) -> Result<(), FluidError> {
+        self.record(Input::Evolve(n));
+        self.world.evolve(n)?;
+        if self.history.is_some() {
+            let (time, snapshot) = (self.world.time(), self.save_snapshot());
+            if let Some(history) = self.history.as_mut() {
+                history.push(time, snapshot);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the steps due after `dt` seconds of real time, e.g. the time since the last
+    /// animation frame, at the current speed. Returns how many steps were run.
+    pub fn advance(&mut self, dt: f64) -> Result<usize, FluidError> {
+        finite("dt", &[dt])?;
+        if dt < 0. {
+            return Err(FluidError::InvalidArgument {
+                name: "dt",
+                reason: "must not be negative",
+            });
+        }
+        let steps = self.scheduler.steps(dt, self.world.step());
+        if steps > 0 {
+            self.evolve(steps)?;
+        }
+        Ok(steps)
+    }
+
+    /// Stops `advance` from stepping, `single_step` still works.
+    pub fn pause(&mut self) {
+        self.scheduler.paused = true;
+        self.scheduler.reset();
+    }
+
+    pub fn resume(&mut self) {
+        self.scheduler.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.scheduler.paused
+    }
+
+    /// Runs exactly one step, also while paused.
+    pub fn single_step(&mut self) -> Result<(), FluidError> {
+        self.evolve(1)
+    }
+
+    /// Simulated seconds per real second of `advance`, 1 by default.
+    pub fn set_speed(&mut self, speed: f64) -> Result<(), FluidError> {
+        positive("speed", speed)?;
+        self.scheduler.speed = speed;
+        Ok(())
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.scheduler.speed
+    }
+
+    /// Most steps a single `advance` runs, the time beyond it is dropped.
+    pub fn set_max_substeps(&mut self, max_substeps: usize) -> Result<(), FluidError> {
+        if max_substeps == 0 {
+            return Err(FluidError::InvalidArgument {
+                name: "max_substeps",
+                reason: "must be at least 1",
+            });
         }
+        self.scheduler.max_substeps = max_substeps;
+        Ok(())
     }
 
-    pub fn evolve(&mut self, n: usize) {
-        self.world.evolve(n);
+    pub fn max_substeps(&self) -> usize {
+        self.scheduler.max_substeps
     }
 
     pub fn remove_mouse_pos(&mut self) {
+        self.record(Input::RemoveMousePos);
         self.draw_context.mouse_pos = None;
         self.world.update_mouse_pos(None, false);
     }
 
-    pub fn update_mouse_pos(&mut self, x: f64, y: f64, is_pressing: bool) {
+    pub fn update_mouse_pos(
+        &mut self,
+        x: f64,
+        y: f64,
+        is_pressing: bool,
+    ) -> Result<(), FluidError> {
+        finite("mouse position", &[x, y])?;
+        self.record(Input::MousePos { x, y, is_pressing });
         self.draw_context.mouse_pos = Some(V2::new(x, y));
         self.world
-            .update_mouse_pos(self.draw_context.mouse_pos.clone(), is_pressing);
+            .update_mouse_pos(self.draw_context.mouse_pos, is_pressing);
+        Ok(())
+    }
+
+    /// Presses a pointer, e.g. a finger from a `pointerdown` event, with its own tool.
+    pub fn add_pointer(
+        &mut self,
+        id: u32,
+        x: f64,
+        y: f64,
+        pressure: f64,
+        tool: Tool,
+    ) -> Result<(), FluidError> {
+        finite("pointer", &[x, y, pressure])?;
+        self.world.add_pointer(id, V2::new(x, y), pressure, tool);
+        Ok(())
+    }
+
+    pub fn move_pointer(
+        &mut self,
+        id: u32,
+        x: f64,
+        y: f64,
+        pressure: f64,
+    ) -> Result<(), FluidError> {
+        finite("pointer", &[x, y, pressure])?;
+        self.world.move_pointer(id, V2::new(x, y), pressure);
+        Ok(())
+    }
+
+    pub fn remove_pointer(&mut self, id: u32) {
+        self.world.remove_pointer(id);
+    }
+
+    pub fn pointer_count(&self) -> usize {
+        self.world.pointer_count()
+    }
+
+    pub fn tool(&self) -> Tool {
+        self.world.tool()
+    }
+
+    /// Gravity pointing the same way everywhere, `(0, 30)` by default.
+    pub fn set_gravity(&mut self, x: f64, y: f64) -> Result<(), FluidError> {
+        finite("gravity", &[x, y])?;
+        self.world.set_gravity(Gravity::Uniform(V2::new(x, y)));
+        Ok(())
+    }
+
+    /// Gravity pulling towards a point, like a planet.
+    pub fn set_point_gravity(&mut self, x: f64, y: f64, strength: f64) -> Result<(), FluidError> {
+        finite("gravity", &[x, y, strength])?;
+        let center = V2::new(x, y);
+        self.world.set_gravity(Gravity::Point { center, strength });
+        Ok(())
+    }
+
+    pub fn set_zero_gravity(&mut self) {
+        self.world.set_gravity(Gravity::Zero);
+    }
+
+    /// Gravity following the tilt of the device, from the `beta` and `gamma` angles,
+    /// in degrees, of a `deviceorientation` event.
+    pub fn set_gravity_from_orientation(
+        &mut self,
+        beta: f64,
+        gamma: f64,
+        magnitude: f64,
+    ) -> Result<(), FluidError> {
+        finite("orientation", &[beta, gamma, magnitude])?;
+        let gravity = Gravity::from_orientation(beta, gamma, magnitude);
+        self.world.set_gravity(gravity);
+        Ok(())
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.world.set_tool(tool, self.draw_context.mouse_radius);
+    }
+
+    pub fn tool_radius(&self) -> f64 {
+        self.draw_context.mouse_radius
+    }
+
+    pub fn set_tool_radius(&mut self, radius: f64) -> Result<(), FluidError> {
+        positive("tool radius", radius)?;
+        self.draw_context.mouse_radius = radius;
+        self.world.set_tool(self.world.tool(), radius);
+        Ok(())
+    }
+
+    /// Makes the paint and spawn tools colour particles.
+    pub fn set_paint_color(&mut self, r: u8, g: u8, b: u8, a: u8) {
+        self.world.set_paint(Paint::Color(Rgba::new(r, g, b, a)));
+    }
+
+    /// Makes the paint and spawn tools move particles to a phase.
+    pub fn set_paint_phase(&mut self, phase: u32) -> Result<(), FluidError> {
+        phases::registered(phase, self.world.phase_count())?;
+        self.world.set_paint(Paint::Phase(phase));
+        Ok(())
+    }
+
+    pub fn params(&self) -> SimulationParams {
+        self.world.params()
+    }
+
+    pub fn set_params(&mut self, params: SimulationParams) -> Result<(), FluidError> {
+        params.validate()?;
+        self.world.set_params(params);
+        Ok(())
+    }
+
+    pub fn solver_stats(&self) -> SolverStats {
+        self.world.solver_stats()
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.world.particles().len()
+    }
+
+    /// Adds a particle and returns its index.
+    pub fn add_particle(&mut self, x: f64, y: f64, vx: f64, vy: f64) -> Result<usize, FluidError> {
+        finite("particle", &[x, y, vx, vy])?;
+        Ok(self.world.add_particle(V2::new(x, y), V2::new(vx, vy)))
+    }
+
+    /// Fills a rectangle with a block of particles at rest, `spacing` apart.
+    /// Returns how many were added.
+    pub fn add_particles_in_rect(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        spacing: f64,
+    ) -> Result<usize, FluidError> {
+        finite("rect", &[x0, y0, x1, y1])?;
+        particle::validate_spacing(spacing)?;
+        let rect = kurbo::Rect::new(x0, y0, x1, y1).abs();
+        Ok(self
+            .world
+            .add_particles_in_shape(&rect.to_path(0.1), spacing))
+    }
+
+    /// Fills a circle with a block of particles at rest, `spacing` apart.
+    /// Returns how many were added.
+    pub fn add_particles_in_circle(
+        &mut self,
+        x: f64,
+        y: f64,
+        radius: f64,
+        spacing: f64,
+    ) -> Result<usize, FluidError> {
+        finite("center", &[x, y])?;
+        positive("radius", radius)?;
+        particle::validate_spacing(spacing)?;
+        let circle = kurbo::Circle::new((x, y), radius);
+        Ok(self
+            .world
+            .add_particles_in_shape(&circle.to_path(0.1), spacing))
+    }
+
+    /// Removes the particles within `radius` of a point and returns how many were removed.
+    pub fn remove_particles_in_radius(
+        &mut self,
+        x: f64,
+        y: f64,
+        radius: f64,
+    ) -> Result<usize, FluidError> {
+        finite("center", &[x, y])?;
+        non_negative("radius", radius)?;
+        Ok(self
+            .world
+            .remove_particles_in_radius(&V2::new(x, y), radius))
+    }
+
+    /// Removes every particle.
+    pub fn clear(&mut self) {
+        self.world.clear();
+    }
+
+    /// Registers a named scalar channel on every particle and returns its index.
+    pub fn add_channel(&mut self, name: &str) -> usize {
+        self.world.add_channel(name)
+    }
+
+    pub fn channel_names(&self) -> Vec<String> {
+        self.world.channels().names().to_vec()
+    }
+
+    pub fn particle_mass(&self, index: usize) -> Option<f64> {
+        self.world.particles().get(index).map(|p| p.mass)
+    }
+
+    pub fn set_particle_mass(&mut self, index: usize, mass: f64) -> Result<(), FluidError> {
+        positive("mass", mass)?;
+        if let Some(particle) = self.world.particles_mut().get_mut(index) {
+            particle.mass = mass;
+        }
+        Ok(())
+    }
+
+    pub fn particle_phase(&self, index: usize) -> Option<u32> {
+        self.world.particles().get(index).map(|p| p.phase)
+    }
+
+    /// Moves the particle to another fluid, which also sets its mass and colour.
+    pub fn set_particle_phase(&mut self, index: usize, phase: u32) -> Result<(), FluidError> {
+        phases::registered(phase, self.world.phase_count())?;
+        self.world.set_particle_phase(index, phase);
+        Ok(())
+    }
+
+    /// Registers an immiscible fluid and returns its phase id. `density` and
+    /// `viscosity` are relative to the default fluid, phase 0.
+    pub fn add_phase(
+        &mut self,
+        density: f64,
+        viscosity: f64,
+        r: u8,
+        g: u8,
+        b: u8,
+        a: u8,
+    ) -> Result<u32, FluidError> {
+        let phase = Phase {
+            density,
+            viscosity,
+            color: Rgba::new(r, g, b, a),
+        };
+        phase.validate()?;
+        Ok(self.world.add_phase(phase))
+    }
+
+    /// Moves every particle inside the rectangle to the given phase, used to layer fluids.
+    pub fn set_phase_in_rect(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        phase: u32,
+    ) -> Result<(), FluidError> {
+        finite("rect", &[x0, y0, x1, y1])?;
+        phases::registered(phase, self.world.phase_count())?;
+        let rect = kurbo::Rect::new(x0, y0, x1, y1).abs();
+        let inside: Vec<usize> = self
+            .world
+            .particles()
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| rect.contains((p.position.x, p.position.y).into()))
+            .map(|(i, _)| i)
+            .collect();
+        inside
+            .into_iter()
+            .for_each(|i| self.world.set_particle_phase(i, phase));
+        Ok(())
+    }
+
+    pub fn set_particle_color(&mut self, index: usize, r: u8, g: u8, b: u8, a: u8) {
+        if let Some(particle) = self.world.particles_mut().get_mut(index) {
+            particle.color = Rgba::new(r, g, b, a);
+        }
+    }
+
+    pub fn particle_channel(&self, index: usize, name: &str) -> Option<f64> {
+        let channel = self.world.channels().index_of(name)?;
+        self.world
+            .particles()
+            .get(index)?
+            .channels
+            .get(channel)
+            .copied()
+    }
+
+    /// Returns false when the particle or the channel does not exist.
+    pub fn set_particle_channel(&mut self, index: usize, name: &str, value: f64) -> bool {
+        let Some(channel) = self.world.channels().index_of(name) else {
+            return false;
+        };
+        match self
+            .world
+            .particles_mut()
+            .get_mut(index)
+            .and_then(|particle| particle.channels.get_mut(channel))
+        {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn add_circle_obstacle(
+        &mut self,
+        x: f64,
+        y: f64,
+        radius: f64,
+        restitution: f64,
+        friction: f64,
+    ) -> Result<usize, FluidError> {
+        finite("center", &[x, y])?;
+        positive("radius", radius)?;
+        finite("response", &[restitution, friction])?;
+        let shape = ObstacleShape::Circle(kurbo::Circle::new((x, y), radius));
+        Ok(self
+            .world
+            .add_obstacle(Obstacle::new(shape, restitution, friction)))
+    }
+
+    pub fn add_rect_obstacle(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        restitution: f64,
+        friction: f64,
+    ) -> Result<usize, FluidError> {
+        finite("rect", &[x0, y0, x1, y1])?;
+        finite("response", &[restitution, friction])?;
+        let shape = ObstacleShape::Rect(kurbo::Rect::new(x0, y0, x1, y1).abs());
+        Ok(self
+            .world
+            .add_obstacle(Obstacle::new(shape, restitution, friction)))
+    }
+
+    /// `points` holds the polygon vertices as consecutive x, y pairs, at least 3 of them.
+    pub fn add_polygon_obstacle(
+        &mut self,
+        points: &[f64],
+        restitution: f64,
+        friction: f64,
+    ) -> Result<usize, FluidError> {
+        if points.len() < 6 || !points.len().is_multiple_of(2) {
+            return Err(FluidError::InvalidArgument {
+                name: "points",
+                reason: "must hold at least 3 x, y pairs",
+            });
+        }
+        finite("points", points)?;
+        finite("response", &[restitution, friction])?;
+        let points: Vec<V2> = points
+            .chunks_exact(2)
+            .map(|xy| V2::new(xy[0], xy[1]))
+            .collect();
+        Ok(self
+            .world
+            .add_obstacle(Obstacle::polygon(&points, restitution, friction)))
     }
 
-    pub fn draw(&self, ctx: JsValue) {
-        self._draw(ctx);
+    /// Adds an obstacle from svg path data, closed subpaths are solid and open ones
+    /// behave like thin walls.
+    pub fn add_path_obstacle(
+        &mut self,
+        svg: &str,
+        restitution: f64,
+        friction: f64,
+    ) -> Result<usize, FluidError> {
+        finite("response", &[restitution, friction])?;
+        let path = kurbo::BezPath::from_svg(svg).map_err(|e| FluidError::Path(e.to_string()))?;
+        let shape = ObstacleShape::Path(path);
+        Ok(self
+            .world
+            .add_obstacle(Obstacle::new(shape, restitution, friction)))
+    }
+
+    pub fn remove_obstacle(&mut self, index: usize) {
+        self.world.remove_obstacle(index);
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.world.clear_obstacles();
+    }
+
+    pub fn add_uniform_field(&mut self, ax: f64, ay: f64) -> Result<u32, FluidError> {
+        finite("acceleration", &[ax, ay])?;
+        let acceleration = V2::new(ax, ay);
+        Ok(self
+            .world
+            .add_force_field(Box::new(UniformField { acceleration })))
+    }
+
+    /// Positive strengths attract particles, negative ones repel them.
+    pub fn add_radial_field(
+        &mut self,
+        x: f64,
+        y: f64,
+        radius: f64,
+        strength: f64,
+        falloff: f64,
+    ) -> Result<u32, FluidError> {
+        finite("center", &[x, y])?;
+        positive("radius", radius)?;
+        finite("strength", &[strength])?;
+        non_negative("falloff", falloff)?;
+        Ok(self.world.add_force_field(Box::new(RadialField {
+            center: V2::new(x, y),
+            radius,
+            strength,
+            falloff,
+        })))
+    }
+
+    pub fn add_vortex_field(
+        &mut self,
+        x: f64,
+        y: f64,
+        radius: f64,
+        strength: f64,
+        falloff: f64,
+    ) -> Result<u32, FluidError> {
+        finite("center", &[x, y])?;
+        positive("radius", radius)?;
+        finite("strength", &[strength])?;
+        non_negative("falloff", falloff)?;
+        Ok(self.world.add_force_field(Box::new(VortexField {
+            center: V2::new(x, y),
+            radius,
+            strength,
+            falloff,
+        })))
+    }
+
+    pub fn add_drag_field(&mut self, coefficient: f64) -> Result<u32, FluidError> {
+        non_negative("coefficient", coefficient)?;
+        Ok(self
+            .world
+            .add_force_field(Box::new(DragField { coefficient })))
+    }
+
+    pub fn add_noise_field(
+        &mut self,
+        strength: f64,
+        scale: f64,
+        speed: f64,
+    ) -> Result<u32, FluidError> {
+        finite("strength", &[strength])?;
+        positive("scale", scale)?;
+        finite("speed", &[speed])?;
+        Ok(self.world.add_force_field(Box::new(NoiseField {
+            strength,
+            scale,
+            speed,
+        })))
+    }
+
+    /// Adds a field computed by `function(x, y, vx, vy, time)`, which returns `[ax, ay]`.
+    pub fn add_function_field(&mut self, function: js_sys::Function) -> u32 {
+        self.world
+            .add_force_field(Box::new(JsFunctionField { function }))
+    }
+
+    pub fn remove_force_field(&mut self, id: u32) -> bool {
+        self.world.remove_force_field(id)
+    }
+
+    pub fn clear_force_fields(&mut self) {
+        self.world.clear_force_fields();
+    }
+
+    pub fn add_point_emitter(
+        &mut self,
+        x: f64,
+        y: f64,
+        params: EmitterParams,
+    ) -> Result<usize, FluidError> {
+        finite("position", &[x, y])?;
+        params.validate(self.world.phase_count())?;
+        let shape = EmitterShape::Point(V2::new(x, y));
+        Ok(self.world.add_emitter(Emitter::new(shape, params)))
+    }
+
+    pub fn add_line_emitter(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        params: EmitterParams,
+    ) -> Result<usize, FluidError> {
+        finite("line", &[x0, y0, x1, y1])?;
+        params.validate(self.world.phase_count())?;
+        let shape = EmitterShape::Line(V2::new(x0, y0), V2::new(x1, y1));
+        Ok(self.world.add_emitter(Emitter::new(shape, params)))
+    }
+
+    /// Emits across a segment of `width` perpendicular to the emission velocity.
+    pub fn add_nozzle_emitter(
+        &mut self,
+        x: f64,
+        y: f64,
+        width: f64,
+        params: EmitterParams,
+    ) -> Result<usize, FluidError> {
+        finite("position", &[x, y])?;
+        positive("width", width)?;
+        params.validate(self.world.phase_count())?;
+        let position = V2::new(x, y);
+        let shape = EmitterShape::Nozzle { position, width };
+        Ok(self.world.add_emitter(Emitter::new(shape, params)))
+    }
+
+    pub fn remove_emitter(&mut self, index: usize) {
+        self.world.remove_emitter(index);
+    }
+
+    pub fn clear_emitters(&mut self) {
+        self.world.clear_emitters();
+    }
+
+    pub fn add_circle_sink(&mut self, x: f64, y: f64, radius: f64) -> Result<usize, FluidError> {
+        finite("center", &[x, y])?;
+        positive("radius", radius)?;
+        let circle = kurbo::Circle::new((x, y), radius);
+        Ok(self.world.add_sink(Sink::Circle(circle)))
+    }
+
+    pub fn add_rect_sink(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+    ) -> Result<usize, FluidError> {
+        finite("rect", &[x0, y0, x1, y1])?;
+        let rect = kurbo::Rect::new(x0, y0, x1, y1).abs();
+        Ok(self.world.add_sink(Sink::Rect(rect)))
+    }
+
+    pub fn remove_sink(&mut self, index: usize) {
+        self.world.remove_sink(index);
+    }
+
+    pub fn clear_sinks(&mut self) {
+        self.world.clear_sinks();
+    }
+
+    pub fn sdf_circle(&mut self, x: f64, y: f64, radius: f64, op: SdfOp) -> Result<(), FluidError> {
+        finite("center", &[x, y])?;
+        positive("radius", radius)?;
+        let center = V2::new(x, y);
+        self.world
+            .combine_sdf(SdfShape::Circle { center, radius }, op);
+        Ok(())
+    }
+
+    pub fn sdf_rect(
+        &mut self,
+        x0: f64,
+        y0: f64,
+        x1: f64,
+        y1: f64,
+        op: SdfOp,
+    ) -> Result<(), FluidError> {
+        finite("rect", &[x0, y0, x1, y1])?;
+        let rect = kurbo::Rect::new(x0, y0, x1, y1).abs();
+        self.world.combine_sdf(SdfShape::Rect(rect), op);
+        Ok(())
+    }
+
+    /// Combines svg path data with the distance field boundary, closed subpaths
+    /// are solid and open ones behave like thin walls.
+    pub fn sdf_path(&mut self, svg: &str, op: SdfOp) -> Result<(), FluidError> {
+        let path = kurbo::BezPath::from_svg(svg).map_err(|e| FluidError::Path(e.to_string()))?;
+        self.world.combine_sdf(SdfShape::Path(path), op);
+        Ok(())
+    }
+
+    /// Fails when no shape was added to the distance field boundary yet.
+    pub fn set_sdf_response(&mut self, restitution: f64, friction: f64) -> Result<(), FluidError> {
+        finite("response", &[restitution, friction])?;
+        if self.world.set_sdf_response(restitution, friction) {
+            Ok(())
+        } else {
+            Err(FluidError::MissingSdf)
+        }
+    }
+
+    pub fn clear_sdf(&mut self) {
+        self.world.clear_sdf();
+    }
+
+    pub fn draw(&self, ctx: JsValue) -> Result<(), FluidError> {
+        let mut ctx: CanvasRenderingContext2d =
+            ctx.dyn_into().map_err(|_| FluidError::NotACanvasContext)?;
+        self.world.draw(&mut ctx, &self.draw_context)
+    }
+
+    /// What `draw` would draw, serialized to be posted from a worker running the
+    /// simulation to a `FrameRenderer` on the main thread.
+    pub fn frame_packet(&self) -> Result<Vec<u8>, FluidError> {
+        let mut packet = FramePacket::new(self.draw_context.width, self.draw_context.height);
+        self.world.draw(&mut packet, &self.draw_context)?;
+        Ok(packet.to_bytes())
     }
 }
 
 impl CanvasDriven {
-    fn _draw(&self, ctx: JsValue) -> Option<()> {
-        let ctx: CanvasRenderingContext2d = ctx.dyn_into().ok()?;
-        self.world.draw(&ctx, &self.draw_context);
-        // web_sys::console::log_1(&"drawn".into());
-        Some(())
+    fn build(scene: &Scene) -> Result<CanvasDriven, FluidError> {
+        scene.validate()?;
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            width: scene.width,
+            height: scene.height,
+            particles: scene.random_particles,
+            tree_type: scene.tree_type,
+            params: scene.params,
+            seed: scene.seed,
+        })?;
+        canvas.world.apply_scene(scene);
+        Ok(canvas)
+    }
+
+    /// Writes the particles and their attributes in a file format.
+    pub fn write_particles(&self, format: ExportFormat, writer: &mut impl Write) -> io::Result<()> {
+        export::write_particles(
+            format,
+            self.world.particles(),
+            self.world.channels(),
+            writer,
+        )
+    }
+
+    fn restore(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = Reader::new(bytes, SnapshotKind::Fluid)?;
+        let tree_type: TreeType = reader.get()?;
+        let world = match tree_type {
+            TreeType::Hilbert => {
+                decode_world::<SpaceFillingTree<HilbertCurve<Particle>>>(&mut reader)?
+            }
+            TreeType::ZOrder => {
+                decode_world::<SpaceFillingTree<ZOrderCurve<Particle>>>(&mut reader)?
+            }
+            TreeType::Quad => decode_world::<QuadTree<Particle>>(&mut reader)?,
+            TreeType::RStar => decode_world::<RStartree<Particle>>(&mut reader)?,
+            TreeType::HashGrid => decode_world::<HashGrid<Particle>>(&mut reader)?,
+        };
+        reader.finish()?;
+        let dimensions = world.dimensions();
+        self.draw_context = DrawContext {
+            mouse_pos: None,
+            mouse_radius: world.tool_radius(),
+            width: dimensions.x,
+            height: dimensions.y,
+        };
+        self.world = world;
+        self.tree_type = tree_type;
+        // neither can be continued from another state
+        self.recording = None;
+        self.replay.clear();
+        self.scheduler.reset();
+        Ok(())
+    }
+
+    fn play(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let recording = Recording::from_bytes(bytes)?;
+        self.restore(&recording.snapshot)?;
+        self.draw_context.mouse_pos = recording.pointers.mouse_pos;
+        self.world.restore_pointer_state(recording.pointers);
+        self.replay = recording.events.into();
+        Ok(())
+    }
+
+    fn record(&mut self, input: Input) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(self.world.time(), input);
+        }
     }
 }
 
 trait ParticleWorld {
-    fn evolve(&mut self, n: usize);
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext);
+    fn evolve(&mut self, n: usize) -> Result<(), FluidError>;
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError>;
     fn update_mouse_pos(&mut self, mouse_pos: Option<V2>, is_pressing: bool);
+    fn add_pointer(&mut self, id: u32, position: V2, pressure: f64, tool: Tool);
+    fn move_pointer(&mut self, id: u32, position: V2, pressure: f64);
+    fn remove_pointer(&mut self, id: u32);
+    fn pointer_count(&self) -> usize;
+    fn pointer_state(&self) -> PointerState;
+    fn restore_pointer_state(&mut self, state: PointerState);
+    fn tool(&self) -> Tool;
+    fn set_gravity(&mut self, gravity: Gravity);
+    fn set_tool(&mut self, tool: Tool, radius: f64);
+    fn set_paint(&mut self, paint: Paint);
+    fn params(&self) -> SimulationParams;
+    fn set_params(&mut self, params: SimulationParams);
+    fn solver_stats(&self) -> SolverStats;
+    fn add_particle(&mut self, position: V2, velocity: V2) -> usize;
+    fn add_particles_in_shape(&mut self, shape: &BezPath, spacing: f64) -> usize;
+    fn remove_particles_in_radius(&mut self, center: &V2, radius: f64) -> usize;
+    fn clear(&mut self);
+    fn particles(&self) -> &[Particle];
+    fn particles_mut(&mut self) -> &mut [Particle];
+    fn channels(&self) -> &Channels;
+    fn add_channel(&mut self, name: &str) -> usize;
+    fn add_phase(&mut self, phase: Phase) -> u32;
+    fn phase_count(&self) -> usize;
+    fn set_particle_phase(&mut self, index: usize, phase: u32);
+    fn add_obstacle(&mut self, obstacle: Obstacle) -> usize;
+    fn remove_obstacle(&mut self, index: usize);
+    fn clear_obstacles(&mut self);
+    fn add_force_field(&mut self, field: Box<dyn ForceField>) -> u32;
+    fn remove_force_field(&mut self, id: u32) -> bool;
+    fn clear_force_fields(&mut self);
+    fn add_emitter(&mut self, emitter: Emitter) -> usize;
+    fn remove_emitter(&mut self, index: usize);
+    fn clear_emitters(&mut self);
+    fn add_sink(&mut self, sink: Sink) -> usize;
+    fn remove_sink(&mut self, index: usize);
+    fn clear_sinks(&mut self);
+    fn combine_sdf(&mut self, shape: SdfShape, op: SdfOp);
+    /// Returns false when there is no distance field.
+    fn set_sdf_response(&mut self, restitution: f64, friction: f64) -> bool;
+    fn clear_sdf(&mut self);
+    fn dimensions(&self) -> V2;
+    fn tool_radius(&self) -> f64;
+    fn time(&self) -> f64;
+    /// Simulated seconds per step.
+    fn step(&self) -> f64;
+    fn apply_scene(&mut self, scene: &Scene);
+    fn to_scene(&self, tree_type: TreeType) -> Scene;
+    fn migrate(self: Box<Self>, tree_type: TreeType) -> Box<dyn ParticleWorld>;
+    fn encode(&self, writer: &mut Writer);
+}
+
+fn migrate_world<T: GeoQuery<Particle>>(
+    world: World<T>,
+    tree_type: TreeType,
+) -> Box<dyn ParticleWorld> {
+    match tree_type {
+        TreeType::Hilbert => {
+            Box::new(world.with_tree::<SpaceFillingTree<HilbertCurve<Particle>>>())
+        }
+        TreeType::ZOrder => Box::new(world.with_tree::<SpaceFillingTree<ZOrderCurve<Particle>>>()),
+        TreeType::Quad => Box::new(world.with_tree::<QuadTree<Particle>>()),
+        TreeType::RStar => Box::new(world.with_tree::<RStartree<Particle>>()),
+        TreeType::HashGrid => Box::new(world.with_tree::<HashGrid<Particle>>()),
+    }
+}
+
+fn decode_world<T: GeoQuery<Particle> + Drawable + 'static>(
+    reader: &mut Reader,
+) -> Result<Box<dyn ParticleWorld>, SnapshotError> {
+    Ok(Box::new(World::<T>::decode(reader)?))
 }
 
 impl<T> ParticleWorld for World<T>
 where
     T: GeoQuery<Particle> + Drawable,
 {
-    fn evolve(&mut self, n: usize) {
-        World::<T>::evolve(self, n);
+    fn evolve(&mut self, n: usize) -> Result<(), FluidError> {
+        World::<T>::checked_evolve(self, n)
     }
 
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) {
-        Drawable::draw(self, ctx, draw_context);
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError> {
+        Drawable::draw(self, ctx, draw_context)
     }
 
     fn update_mouse_pos(&mut self, mouse_pos: Option<V2>, is_pressing: bool) {
         World::<T>::update_mouse_pos(self, mouse_pos, is_pressing);
     }
+
+    fn add_pointer(&mut self, id: u32, position: V2, pressure: f64, tool: Tool) {
+        World::<T>::add_pointer(self, id, position, pressure, tool);
+    }
+
+    fn move_pointer(&mut self, id: u32, position: V2, pressure: f64) {
+        World::<T>::move_pointer(self, id, position, pressure);
+    }
+
+    fn remove_pointer(&mut self, id: u32) {
+        World::<T>::remove_pointer(self, id);
+    }
+
+    fn pointer_count(&self) -> usize {
+        self.pointers.len()
+    }
+
+    fn pointer_state(&self) -> PointerState {
+        World::<T>::pointer_state(self)
+    }
+
+    fn restore_pointer_state(&mut self, state: PointerState) {
+        World::<T>::restore_pointer_state(self, state);
+    }
+
+    fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    fn set_gravity(&mut self, gravity: Gravity) {
+        self.gravity = gravity;
+    }
+
+    fn set_tool(&mut self, tool: Tool, radius: f64) {
+        World::<T>::set_tool(self, tool, radius);
+    }
+
+    fn set_paint(&mut self, paint: Paint) {
+        self.paint = paint;
+    }
+
+    fn params(&self) -> SimulationParams {
+        self.params
+    }
+
+    fn set_params(&mut self, params: SimulationParams) {
+        World::<T>::set_params(self, params);
+    }
+
+    fn solver_stats(&self) -> SolverStats {
+        self.stats
+    }
+
+    fn add_particle(&mut self, position: V2, velocity: V2) -> usize {
+        World::<T>::add_particle(self, position, velocity)
+    }
+
+    fn add_particles_in_shape(&mut self, shape: &BezPath, spacing: f64) -> usize {
+        World::<T>::add_particles_in_shape(self, shape, spacing)
+    }
+
+    fn remove_particles_in_radius(&mut self, center: &V2, radius: f64) -> usize {
+        World::<T>::remove_particles_in_radius(self, center, radius)
+    }
+
+    fn clear(&mut self) {
+        World::<T>::clear(self);
+    }
+
+    fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    fn particles_mut(&mut self) -> &mut [Particle] {
+        &mut self.particles
+    }
+
+    fn channels(&self) -> &Channels {
+        &self.channels
+    }
+
+    fn add_channel(&mut self, name: &str) -> usize {
+        World::<T>::add_channel(self, name)
+    }
+
+    fn add_phase(&mut self, phase: Phase) -> u32 {
+        World::<T>::add_phase(self, phase)
+    }
+
+    fn phase_count(&self) -> usize {
+        self.phases.len()
+    }
+
+    fn set_particle_phase(&mut self, index: usize, phase: u32) {
+        World::<T>::set_particle_phase(self, index, phase);
+    }
+
+    fn add_obstacle(&mut self, obstacle: Obstacle) -> usize {
+        World::<T>::add_obstacle(self, obstacle)
+    }
+
+    fn remove_obstacle(&mut self, index: usize) {
+        World::<T>::remove_obstacle(self, index);
+    }
+
+    fn clear_obstacles(&mut self) {
+        World::<T>::clear_obstacles(self);
+    }
+
+    fn add_force_field(&mut self, field: Box<dyn ForceField>) -> u32 {
+        World::<T>::add_force_field(self, field)
+    }
+
+    fn remove_force_field(&mut self, id: u32) -> bool {
+        World::<T>::remove_force_field(self, id)
+    }
+
+    fn clear_force_fields(&mut self) {
+        World::<T>::clear_force_fields(self);
+    }
+
+    fn add_emitter(&mut self, emitter: Emitter) -> usize {
+        World::<T>::add_emitter(self, emitter)
+    }
+
+    fn remove_emitter(&mut self, index: usize) {
+        World::<T>::remove_emitter(self, index);
+    }
+
+    fn clear_emitters(&mut self) {
+        self.emitters.clear();
+    }
+
+    fn add_sink(&mut self, sink: Sink) -> usize {
+        World::<T>::add_sink(self, sink)
+    }
+
+    fn remove_sink(&mut self, index: usize) {
+        World::<T>::remove_sink(self, index);
+    }
+
+    fn clear_sinks(&mut self) {
+        self.sinks.clear();
+    }
+
+    fn combine_sdf(&mut self, shape: SdfShape, op: SdfOp) {
+        World::<T>::combine_sdf(self, shape, op);
+    }
+
+    fn set_sdf_response(&mut self, restitution: f64, friction: f64) -> bool {
+        let Some(sdf) = self.sdf.as_mut() else {
+            return false;
+        };
+        sdf.restitution = restitution;
+        sdf.friction = friction;
+        true
+    }
+
+    fn clear_sdf(&mut self) {
+        self.sdf = None;
+    }
+    fn dimensions(&self) -> V2 {
+        self.dimensions
+    }
+
+    fn tool_radius(&self) -> f64 {
+        self.tool_radius
+    }
+
+    fn time(&self) -> f64 {
+        self.time
+    }
+
+    fn step(&self) -> f64 {
+        self.params.dt
+    }
+
+    fn apply_scene(&mut self, scene: &Scene) {
+        World::<T>::apply_scene(self, scene);
+    }
+
+    fn to_scene(&self, tree_type: TreeType) -> Scene {
+        World::<T>::to_scene(self, tree_type)
+    }
+
+    fn migrate(self: Box<Self>, tree_type: TreeType) -> Box<dyn ParticleWorld> {
+        migrate_world(*self, tree_type)
+    }
+
+    fn encode(&self, writer: &mut Writer) {
+        World::<T>::encode(self, writer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_tree_type_keeps_the_scene() {
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            particles: 200,
+            seed: Some(2),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        canvas.add_pointer(1, 300., 300., 1., Tool::Drag).unwrap();
+        canvas.add_drag_field(0.1).unwrap();
+        canvas.evolve(5).unwrap();
+        let positions = |canvas: &CanvasDriven| -> Vec<V2> {
+            canvas
+                .world
+                .particles()
+                .iter()
+                .map(|p| p.position)
+                .collect()
+        };
+        [
+            TreeType::ZOrder,
+            TreeType::Hilbert,
+            TreeType::Quad,
+            TreeType::HashGrid,
+        ]
+        .into_iter()
+        .for_each(|tree_type| {
+            let before = positions(&canvas);
+            let snapshot = canvas.save_snapshot();
+            canvas.set_tree_type(tree_type);
+            assert_eq!(positions(&canvas), before);
+            // only the tree type, after the 9 byte header, changes
+            assert_eq!(canvas.save_snapshot()[10..], snapshot[10..]);
+            assert_eq!(canvas.world.pointer_count(), 1);
+            canvas.evolve(5).unwrap();
+        });
+    }
+
+    #[test]
+    fn invalid_arguments_and_instability_are_errors() {
+        let zero_width = CanvasDriven::new(CanvasDrivenArgs {
+            width: 0.,
+            seed: Some(1),
+            ..CanvasDrivenArgs::default()
+        });
+        assert!(matches!(
+            zero_width,
+            Err(FluidError::InvalidArgument { name: "width", .. })
+        ));
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            seed: Some(1),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        assert!(canvas.update_mouse_pos(f64::NAN, 0., false).is_err());
+        assert!(canvas.add_particles_in_circle(100., 100., 50., 0.).is_err());
+        assert!(canvas
+            .add_particles_in_rect(0., 0., 800., 600., 1e-9)
+            .is_err());
+        assert!(canvas.add_polygon_obstacle(&[], 1., 0.).is_err());
+        assert!(canvas
+            .add_polygon_obstacle(&[0., 0., 10., 0.], 1., 0.)
+            .is_err());
+        assert!(canvas
+            .add_polygon_obstacle(&[0., 0., 10., 0., 10.], 1., 0.)
+            .is_err());
+        let mut params = SimulationParams::default();
+        params.dt = 0.;
+        assert!(canvas.set_params(params).is_err());
+        let count = canvas.particle_count();
+        assert!(canvas.remove_particles_in_radius(0., 0., f64::NAN).is_err());
+        assert_eq!(canvas.particle_count(), count);
+        assert!(canvas.add_phase(0., 1., 0, 0, 0, 255).is_err());
+        assert!(canvas.add_radial_field(0., 0., 50., 10., -1.).is_err());
+        assert!(canvas.add_uniform_field(f64::NAN, 0.).is_err());
+        assert!(canvas.add_drag_field(-1.).is_err());
+        assert!(canvas.add_circle_sink(0., 0., f64::INFINITY).is_err());
+        let mut emitter = EmitterParams::default();
+        emitter.rate = f64::NAN;
+        assert!(canvas.add_point_emitter(0., 0., emitter).is_err());
+        let mut emitter = EmitterParams::default();
+        emitter.phase = 7;
+        assert!(canvas.add_point_emitter(0., 0., emitter).is_err());
+        assert!(canvas.set_particle_phase(0, 7).is_err());
+        assert!(canvas.set_paint_phase(7).is_err());
+        assert!(canvas.set_phase_in_rect(0., 0., 10., 10., 7).is_err());
+        assert_eq!(canvas.set_sdf_response(1., 0.), Err(FluidError::MissingSdf));
+
+        // everything a step changes is put back: emitters, the rng, stats and grabs
+        let mut emitter = EmitterParams::default();
+        emitter.rate = 33.;
+        emitter.spread = 0.5;
+        canvas.add_point_emitter(400., 100., emitter).unwrap();
+        canvas
+            .add_pointer(1, 300., 300., 1., Tool::Attract)
+            .unwrap();
+        canvas.set_tool(Tool::Drag);
+        canvas.update_mouse_pos(500., 500., true).unwrap();
+        canvas.evolve(2).unwrap();
+        // a massless particle gets an infinite acceleration
+        canvas.world.particles_mut()[0].mass = 0.;
+        let (before, pointers) = (canvas.save_snapshot(), canvas.world.pointer_state());
+        assert!(matches!(canvas.evolve(3), Err(FluidError::Unstable { .. })));
+        assert_eq!(canvas.save_snapshot(), before);
+        assert_eq!(canvas.world.pointer_state(), pointers);
+        assert!(!pointers.grabs.is_empty());
+        canvas.world.particles_mut()[0].mass = 1.;
+        canvas.evolve(3).unwrap();
+    }
+
+    #[test]
+    fn channels_and_colours_are_kept_per_particle() {
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            particles: 20,
+            seed: Some(3),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        assert_eq!(canvas.add_channel("temperature"), 0);
+        assert_eq!(canvas.add_channel("dye"), 1);
+        assert_eq!(canvas.add_channel("temperature"), 0);
+        assert_eq!(canvas.channel_names(), ["temperature", "dye"]);
+        assert!(canvas.attribute_names().ends_with(&canvas.channel_names()));
+
+        assert!(canvas.set_particle_channel(3, "dye", 2.));
+        assert!(!canvas.set_particle_channel(3, "salt", 1.));
+        assert!(!canvas.set_particle_channel(100, "dye", 1.));
+        assert_eq!(canvas.particle_channel(3, "dye"), Some(2.));
+        assert_eq!(canvas.particle_channel(4, "dye"), Some(0.));
+        let added = canvas.add_particle(100., 100., 0., 0.).unwrap();
+        assert_eq!(canvas.particle_channel(added, "temperature"), Some(0.));
+
+        canvas.set_particle_color(0, 255, 0, 0, 255);
+        canvas.set_particle_color(1, 0, 0, 255, 255);
+        let snapshot = canvas.save_snapshot();
+        canvas.set_particle_channel(3, "dye", 5.);
+        canvas.load_snapshot(&snapshot).unwrap();
+        assert_eq!(canvas.particle_channel(3, "dye"), Some(2.));
+        assert_eq!(canvas.world.particles()[1].color, Rgba::new(0, 0, 255, 255));
+        // colour groups are drawn in a fixed order
+        assert_eq!(
+            canvas.frame_packet().unwrap(),
+            canvas.frame_packet().unwrap()
+        );
+    }
+
+    #[test]
+    fn a_denser_phase_sinks_below_a_lighter_one() {
+        let mut params = SimulationParams::default();
+        params.solver = Solver::PositionBased;
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            width: 120.,
+            height: 100.,
+            particles: 0,
+            params,
+            seed: Some(5),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        canvas
+            .add_particles_in_rect(10., 50., 110., 100., particle::PARTICLE_RADIUS)
+            .unwrap();
+        let heavy = canvas.add_phase(5., 1., 255, 0, 0, 255).unwrap();
+        canvas.set_phase_in_rect(0., 0., 120., 75., heavy).unwrap();
+        let top = canvas
+            .world
+            .particles()
+            .iter()
+            .position(|p| p.position.y < 75.)
+            .unwrap();
+        assert_eq!(canvas.particle_phase(top), Some(heavy));
+        assert_eq!(canvas.particle_mass(top), Some(5.));
+        assert_eq!(
+            canvas.world.particles()[top].color,
+            Rgba::new(255, 0, 0, 255)
+        );
+
+        let mean_depth = |canvas: &CanvasDriven, phase: u32| {
+            let ys: Vec<f64> = canvas
+                .world
+                .particles()
+                .iter()
+                .filter(|p| p.phase == phase)
+                .map(|p| p.position.y)
+                .collect();
+            ys.iter().sum::<f64>() / ys.len() as f64
+        };
+        assert!(mean_depth(&canvas, heavy) < mean_depth(&canvas, 0));
+        canvas.set_gravity(0., 300.).unwrap();
+        canvas.evolve(300).unwrap();
+        assert!(mean_depth(&canvas, heavy) > mean_depth(&canvas, 0));
+    }
 }
@@ -0,0 +1,68 @@
+use super::{
+    particle::{GeoQuery, Particle, World},
+    sph::SMOOTHING_RADIUS,
+    v2::V2,
+};
+
+/// A neighbour found by the tree, referring back to the particle it was copied from
+/// so the iterative solvers can read moved positions without rebuilding the tree.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Neighbour {
+    /// Index in `World::particles`, or in `World::boundary` for boundary particles.
+    index: usize,
+    boundary: bool,
+    /// Offset of a periodic ghost from the particle it copies.
+    shift: V2,
+}
+
+impl Neighbour {
+    pub(super) fn fluid_index(&self) -> Option<usize> {
+        (!self.boundary).then_some(self.index)
+    }
+}
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// Neighbour lists of every fluid particle within the smoothing radius,
+    /// the tree has to be up to date.
+    pub(super) fn find_neighbours(&self) -> Vec<Vec<Neighbour>> {
+        self.particles
+            .iter()
+            .map(|p| {
+                let mut neighbours = Vec::new();
+                self.tree
+                    .query_distance(&p.position, SMOOTHING_RADIUS, |other| {
+                        let boundary = other.is_boundary();
+                        let source = self.source(other);
+                        neighbours.push(Neighbour {
+                            index: other.slot,
+                            boundary,
+                            shift: other.position.sub(&source.position),
+                        });
+                    });
+                neighbours
+            })
+            .collect()
+    }
+
+    /// The particle a value of the tree was copied from, holding the latest SPH state.
+    pub(super) fn source<'a>(&'a self, copy: &'a Particle) -> &'a Particle {
+        if copy.is_boundary() {
+            copy
+        } else {
+            &self.particles[copy.slot]
+        }
+    }
+
+    pub(super) fn neighbour(&self, neighbour: &Neighbour) -> &Particle {
+        if neighbour.boundary {
+            &self.boundary[neighbour.index]
+        } else {
+            &self.particles[neighbour.index]
+        }
+    }
+
+    /// Current position of a neighbour, on the side of the domain its particle sees it.
+    pub(super) fn neighbour_position(&self, neighbour: &Neighbour) -> V2 {
+        self.neighbour(neighbour).position + neighbour.shift
+    }
+}
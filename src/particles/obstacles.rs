@@ -0,0 +1,210 @@
+use kurbo::{BezPath, Circle, ParamCurve, ParamCurveNearest, PathEl, Point, Rect, Shape};
+
+use super::{
+    particle::{Particle, PARTICLE_RADIUS},
+    v2::V2,
+};
+
+/// Particles closer than this to an open path collide with it.
+pub const PATH_THICKNESS: f64 = PARTICLE_RADIUS / 2.;
+const NEAREST_ACCURACY: f64 = 1e-3;
+
+#[derive(Clone, Debug)]
+pub enum ObstacleShape {
+    Circle(Circle),
+    Rect(Rect),
+    /// Closed subpaths are solid, open subpaths behave like thin walls.
+    Path(BezPath),
+}
+
+/// A static solid the particles bounce off.
+#[derive(Clone, Debug)]
+pub struct Obstacle {
+    pub shape: ObstacleShape,
+    /// Fraction of the normal velocity kept after a collision.
+    pub restitution: f64,
+    /// Fraction of the tangential velocity removed by a collision.
+    pub friction: f64,
+}
+
+impl Obstacle {
+    pub fn new(shape: ObstacleShape, restitution: f64, friction: f64) -> Obstacle {
+        Obstacle {
+            shape,
+            restitution,
+            friction,
+        }
+    }
+
+    pub fn polygon(points: &[V2], restitution: f64, friction: f64) -> Obstacle {
+        let mut path = BezPath::new();
+        points.iter().enumerate().for_each(|(i, p)| {
+            if i == 0 {
+                path.move_to((p.x, p.y));
+            } else {
+                path.line_to((p.x, p.y));
+            }
+        });
+        path.close_path();
+        Obstacle::new(ObstacleShape::Path(path), restitution, friction)
+    }
+
+    pub fn to_path(&self) -> BezPath {
+        match &self.shape {
+            ObstacleShape::Circle(circle) => circle.to_path(0.1),
+            ObstacleShape::Rect(rect) => rect.to_path(0.1),
+            ObstacleShape::Path(path) => path.clone(),
+        }
+    }
+
+    /// Point on the surface where a penetrating particle has to be moved to,
+    /// and the outward normal there. None when the point is outside the obstacle.
+    pub fn contact(&self, point: &V2) -> Option<(V2, V2)> {
+        let pt = Point::new(point.x, point.y);
+        match &self.shape {
+            ObstacleShape::Circle(circle) => {
+                let center = V2::new(circle.center.x, circle.center.y);
+                let offset = point.sub(&center);
+                if offset.len() >= circle.radius {
+                    return None;
+                }
+                let normal = if offset.len() < 0.001 {
+                    V2::new(0., -1.)
+                } else {
+                    offset.normalized()
+                };
+                Some((center + circle.radius * normal, normal))
+            }
+            ObstacleShape::Rect(rect) => {
+                if !rect.contains(pt) {
+                    return None;
+                }
+                let sides = [
+                    (pt.x - rect.x0, V2::new(-1., 0.), V2::new(rect.x0, pt.y)),
+                    (rect.x1 - pt.x, V2::new(1., 0.), V2::new(rect.x1, pt.y)),
+                    (pt.y - rect.y0, V2::new(0., -1.), V2::new(pt.x, rect.y0)),
+                    (rect.y1 - pt.y, V2::new(0., 1.), V2::new(pt.x, rect.y1)),
+                ];
+                let (_, normal, surface) = sides.into_iter().min_by(|a, b| a.0.total_cmp(&b.0))?;
+                Some((surface, normal))
+            }
+            ObstacleShape::Path(path) => {
+                let bounds = path.bounding_box().inflate(PATH_THICKNESS, PATH_THICKNESS);
+                if !bounds.contains(pt) {
+                    return None;
+                }
+                let (distance, surface, closed) = path_distance(path, point)?;
+                if distance >= 0. {
+                    return None;
+                }
+                let offset = point.sub(&surface);
+                if closed {
+                    return Some((surface, -1. * offset.normalized()));
+                }
+                let normal = offset.normalized();
+                Some((surface + PATH_THICKNESS * normal, normal))
+            }
+        }
+    }
+
+    /// Projects a penetrating particle back to the surface, reflecting its normal
+    /// velocity with the restitution and damping the tangential one with the friction.
+    pub fn resolve(&self, particle: &mut Particle) {
+        let Some((surface, normal)) = self.contact(&particle.position) else {
+            return;
+        };
+        respond(particle, surface, normal, self.restitution, self.friction);
+    }
+}
+
+pub fn nearest_on_path(path: &[PathEl], point: &V2) -> Option<V2> {
+    let pt = Point::new(point.x, point.y);
+    let (_, nearest) = kurbo::segments(path.iter().copied())
+        .map(|segment| {
+            let nearest = segment.nearest(pt, NEAREST_ACCURACY);
+            (nearest.distance_sq, segment.eval(nearest.t))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))?;
+    Some(V2::new(nearest.x, nearest.y))
+}
+
+/// The subpaths of a path, each starting at a `MoveTo`, and whether they are closed.
+pub fn subpaths(path: &BezPath) -> impl Iterator<Item = (&[PathEl], bool)> {
+    let mut rest = path.elements();
+    std::iter::from_fn(move || {
+        let (_, tail) = rest.split_first()?;
+        let len = 1 + tail
+            .iter()
+            .position(|el| matches!(el, PathEl::MoveTo(_)))
+            .unwrap_or(tail.len());
+        let (subpath, remaining) = rest.split_at(len);
+        rest = remaining;
+        Some((subpath, matches!(subpath.last(), Some(PathEl::ClosePath))))
+    })
+}
+
+/// Signed distance to the nearest subpath, negative inside a closed subpath or within
+/// `PATH_THICKNESS` of an open one, with the nearest point on that subpath and whether
+/// it is closed.
+pub fn path_distance(path: &BezPath, point: &V2) -> Option<(f64, V2, bool)> {
+    let pt = Point::new(point.x, point.y);
+    subpaths(path)
+        .filter_map(|(subpath, closed)| {
+            let nearest = nearest_on_path(subpath, point)?;
+            let d = nearest.distance_to(point);
+            let distance = match closed {
+                true if subpath.winding(pt) != 0 => -d,
+                true => d,
+                false => d - PATH_THICKNESS,
+            };
+            Some((distance, nearest, closed))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+/// Moves the particle to the surface point and, when it moves into the surface,
+/// reflects the normal velocity and damps the tangential one.
+pub fn respond(particle: &mut Particle, surface: V2, normal: V2, restitution: f64, friction: f64) {
+    particle.position = surface;
+    let normal_speed = particle.velocity.x * normal.x + particle.velocity.y * normal.y;
+    if normal_speed >= 0. {
+        return;
+    }
+    let normal_velocity = normal_speed * normal;
+    let tangential_velocity = particle.velocity.sub(&normal_velocity);
+    particle.velocity = (1. - friction) * tangential_velocity + (-restitution) * normal_velocity;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circle_pushes_particles_out_and_reflects_them() {
+        let obstacle = Obstacle::new(ObstacleShape::Circle(Circle::new((0., 0.), 10.)), 0.5, 0.);
+        let mut particle = Particle::new(V2::new(0., -8.), V2::new(1., 4.));
+        obstacle.resolve(&mut particle);
+        assert_eq!(particle.position, V2::new(0., -10.));
+        assert_eq!(particle.velocity, V2::new(1., -2.));
+    }
+
+    #[test]
+    fn closed_subpaths_are_solid_and_open_ones_are_walls() {
+        let square = "M 0 0 L 20 0 L 20 20 L 0 20 Z";
+        let line = "M 40 0 L 60 0";
+        [format!("{square} {line}"), format!("{line} {square}")]
+            .iter()
+            .for_each(|svg| {
+                let path = BezPath::from_svg(svg).unwrap();
+                let obstacle = Obstacle::new(ObstacleShape::Path(path), 1., 0.);
+                let (surface, normal) = obstacle.contact(&V2::new(10., 2.)).unwrap();
+                assert!(surface.distance_to(&V2::new(10., 0.)) < 1e-6);
+                assert_eq!(normal, V2::new(0., -1.));
+                let (surface, normal) = obstacle.contact(&V2::new(50., 1.)).unwrap();
+                assert_eq!(surface, V2::new(50., PATH_THICKNESS));
+                assert_eq!(normal, V2::new(0., 1.));
+                assert!(obstacle.contact(&V2::new(50., 10.)).is_none());
+                assert!(obstacle.contact(&V2::new(30., 10.)).is_none());
+            });
+    }
+}
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::error::{finite, positive, FluidError};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Solver {
+    /// Explicit penalty forces integrated with rk4.
+    Force,
+    /// Position Based Fluids (Macklin & Müller 2013).
+    PositionBased,
+    /// Divergence-free SPH (Bender & Koschier 2015), keeps the fluid incompressible.
+    Incompressible,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WallMode {
+    /// Bounces particles back with the wall's restitution and friction.
+    Reflective,
+    /// Particles leaving the domain come back through the opposite wall, which
+    /// has to be periodic as well.
+    Periodic,
+    /// Particles leaving the domain are deleted.
+    Open,
+    /// Particles hitting the wall stop dead.
+    Absorbing,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default = "WallParams::default", deny_unknown_fields)]
+pub struct WallParams {
+    pub mode: WallMode,
+    /// Fraction of the normal velocity kept by a reflective wall.
+    pub restitution: f64,
+    /// Fraction of the tangential velocity removed by a reflective wall.
+    pub friction: f64,
+}
+
+#[wasm_bindgen]
+impl WallParams {
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Self {
+        WallParams {
+            mode: WallMode::Reflective,
+            restitution: 1.,
+            friction: 0.,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default = "SimulationParams::default", deny_unknown_fields)]
+pub struct SimulationParams {
+    pub solver: Solver,
+    /// Simulated seconds per step.
+    pub dt: f64,
+    /// Strength of the cohesion and curvature forces, 0 disables surface tension.
+    pub surface_tension: f64,
+    /// Density constraint iterations per step of the position based solver.
+    pub pbf_iterations: usize,
+    /// XSPH viscosity coefficient of the position based and incompressible solvers.
+    pub xsph_viscosity: f64,
+    /// Average density error, relative to the rest density, accepted by the incompressible solver.
+    pub density_tolerance: f64,
+    /// Average velocity divergence error, relative to the rest density, accepted by the
+    /// divergence free pass of the incompressible solver.
+    pub divergence_tolerance: f64,
+    /// Iteration cap of each pressure solve of the incompressible solver.
+    pub max_pressure_iterations: usize,
+    /// Samples walls and obstacles as boundary particles that take part in the
+    /// density and pressure computations, instead of only clamping positions.
+    pub boundary_particles: bool,
+    /// Wall at x = 0.
+    pub left_wall: WallParams,
+    /// Wall at x = width.
+    pub right_wall: WallParams,
+    /// Wall at y = 0.
+    pub top_wall: WallParams,
+    /// Wall at y = height.
+    pub bottom_wall: WallParams,
+}
+
+/// Convergence report of the incompressible solver over the last `evolve` call:
+/// iterations are summed over its steps and errors are the worst of its steps.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SolverStats {
+    pub density_iterations: usize,
+    pub density_error: f64,
+    pub divergence_iterations: usize,
+    pub divergence_error: f64,
+}
+
+#[wasm_bindgen]
+impl SimulationParams {
+    #[allow(clippy::should_implement_trait)]
+    pub fn default() -> Self {
+        SimulationParams {
+            solver: Solver::Force,
+            dt: 0.01,
+            surface_tension: 0.,
+            pbf_iterations: 4,
+            xsph_viscosity: 0.05,
+            density_tolerance: 0.01,
+            divergence_tolerance: 0.1,
+            max_pressure_iterations: 50,
+            boundary_particles: false,
+            left_wall: WallParams::default(),
+            right_wall: WallParams::default(),
+            top_wall: WallParams::default(),
+            bottom_wall: WallParams::default(),
+        }
+    }
+}
+
+impl SimulationParams {
+    pub(crate) fn validate(&self) -> Result<(), FluidError> {
+        positive("dt", self.dt)?;
+        finite("surface_tension", &[self.surface_tension])?;
+        finite("xsph_viscosity", &[self.xsph_viscosity])?;
+        positive("density_tolerance", self.density_tolerance)?;
+        positive("divergence_tolerance", self.divergence_tolerance)?;
+        [
+            self.left_wall,
+            self.right_wall,
+            self.top_wall,
+            self.bottom_wall,
+        ]
+        .iter()
+        .try_for_each(|wall| finite("wall", &[wall.restitution, wall.friction]))?;
+        let periodic = |wall: WallParams| wall.mode == WallMode::Periodic;
+        if periodic(self.left_wall) != periodic(self.right_wall)
+            || periodic(self.top_wall) != periodic(self.bottom_wall)
+        {
+            return Err(FluidError::InvalidArgument {
+                name: "wall",
+                reason: "must be periodic on both sides of an axis",
+            });
+        }
+        Ok(())
+    }
+}
@@ -1,20 +1,83 @@
-use super::v2::{ParticleLike, TreeValue, V2};
+use std::collections::BTreeMap;
+
+use kurbo::Shape;
+
+use crate::error::{positive, FluidError};
+
+use super::{
+    attributes::{Channels, Rgba},
+    emitters::{Emitter, Sink},
+    forces::{FieldSpec, ForceField},
+    gravity::Gravity,
+    neighbours::Neighbour,
+    obstacles::Obstacle,
+    params::{SimulationParams, Solver, SolverStats},
+    phases::Phase,
+    rng::Rng,
+    sdf::SdfBoundary,
+    sph::{self, SphState, SMOOTHING_RADIUS},
+    tools::{Grab, Paint, Pointer, Tool},
+    v2::{ParticleLike, TreeValue, V2},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParticleKind {
+    Fluid,
+    /// Static wall sample (Akinci et al. 2012), `volume` corrects for the
+    /// irregular sampling of the boundary.
+    Boundary {
+        volume: f64,
+    },
+}
 
 #[derive(Clone, Debug)]
 pub struct Particle {
     pub position: V2,
     pub velocity: V2,
+    pub kind: ParticleKind,
+    pub mass: f64,
+    /// Material or phase the particle belongs to.
+    pub phase: u32,
+    pub color: Rgba,
+    /// Values of the custom channels registered in `World::channels`.
+    pub channels: Vec<f64>,
+    pub sph: SphState,
+    /// Set while the drag tool holds the particle.
+    pub grab: Option<Grab>,
+    /// Index in `World::particles` or `World::boundary` when the tree was last built.
+    pub(super) slot: usize,
 }
 
 impl Particle {
     pub fn new(position: V2, velocity: V2) -> Particle {
-        Particle { position, velocity }
+        Particle {
+            position,
+            velocity,
+            kind: ParticleKind::Fluid,
+            mass: 1.,
+            phase: 0,
+            color: Rgba::WHITE,
+            channels: Vec::new(),
+            sph: SphState::default(),
+            grab: None,
+            slot: 0,
+        }
+    }
+
+    pub fn is_boundary(&self) -> bool {
+        matches!(self.kind, ParticleKind::Boundary { .. })
+    }
+
+    pub fn is_finite(&self) -> bool {
+        [self.position, self.velocity]
+            .iter()
+            .all(|v| v.x.is_finite() && v.y.is_finite())
     }
 }
 
 impl TreeValue for Particle {
     fn position(&self) -> V2 {
-        self.position.clone()
+        self.position
     }
 
     fn x(&self) -> f64 {
@@ -33,140 +96,530 @@ impl TreeValue for Particle {
 
 pub struct World<T> {
     pub particles: Vec<Particle>,
-    dimensions: V2,
-    gravity: V2,
-    step: f64,
+    pub channels: Channels,
+    /// Fluids of the world, indexed by `Particle::phase`. Phase 0 always exists.
+    pub phases: Vec<Phase>,
+    pub obstacles: Vec<Obstacle>,
+    pub emitters: Vec<Emitter>,
+    pub sinks: Vec<Sink>,
+    /// Boundary particles sampled from the walls and obstacles, inserted in the
+    /// tree next to the fluid when `SimulationParams::boundary_particles` is set.
+    pub(super) boundary: Vec<Particle>,
+    pub(super) boundary_outdated: bool,
+    /// Distance field boundary, an alternative to walls made of particles.
+    pub sdf: Option<SdfBoundary>,
+    pub(super) dimensions: V2,
+    pub gravity: Gravity,
     pub tree: T,
+    /// Force fields acting on the fluid, keyed by the id returned from `add_force_field`.
+    pub force_fields: Vec<(u32, Box<dyn ForceField>)>,
+    pub(super) next_field_id: u32,
+    /// Pressed pointers by pointer id, the mouse is `tools::MOUSE_POINTER`.
+    pub pointers: BTreeMap<u32, Pointer>,
     pub mouse_pos: Option<V2>,
-    pub show_quad_tree: bool,
     pub is_pressing_mouse: bool,
+    /// Tool of the mouse.
+    pub tool: Tool,
+    /// Radius of the tools of all pointers.
+    pub tool_radius: f64,
+    pub paint: Paint,
+    /// Simulated time, advanced by every step.
+    pub time: f64,
+    pub params: SimulationParams,
+    pub stats: SolverStats,
+    /// Source of every random decision of the simulation.
+    pub rng: Rng,
+    pub(super) rest_number_density: f64,
 }
 
-const PRESSURE_MULTIPLIER: f64 = 2000.;
-const STEP: f64 = 0.01;
+pub(super) const PRESSURE_MULTIPLIER: f64 = 2000.;
 const FRICTION: f64 = 0.05;
 pub const PARTICLE_RADIUS: f64 = 4.;
-const MOUSE_FORCE: f64 = -200.;
+/// Closest spacing of the particles filling a shape, denser blocks only blow up and a
+/// tiny spacing would try to allocate a huge number of particles.
+pub const MIN_SPACING: f64 = PARTICLE_RADIUS / 4.;
+const TOOL_RADIUS: f64 = 50.;
+
+/// Checks that particles can be laid out `spacing` apart.
+pub(super) fn validate_spacing(spacing: f64) -> Result<(), FluidError> {
+    positive("spacing", spacing)?;
+    if spacing < MIN_SPACING {
+        return Err(FluidError::InvalidArgument {
+            name: "spacing",
+            reason: "must be at least a quarter of the particle radius",
+        });
+    }
+    Ok(())
+}
 
-fn smoothing_kernel_gradient(d: f64) -> f64 {
+pub(super) fn smoothing_kernel_gradient(d: f64) -> f64 {
     let v = ((PARTICLE_RADIUS - d) / PARTICLE_RADIUS).max(0.);
     v.powi(2)
 }
 
 impl<T: GeoQuery<Particle>> World<T> {
-    pub fn new(dimensions: V2, gravity: V2) -> World<T> {
+    pub fn new(dimensions: V2, gravity: Gravity, seed: u64) -> World<T> {
         World {
             particles: Vec::new(),
+            channels: Channels::default(),
+            phases: vec![Phase::default()],
+            obstacles: Vec::new(),
+            emitters: Vec::new(),
+            sinks: Vec::new(),
+            boundary: Vec::new(),
+            boundary_outdated: true,
+            sdf: None,
             tree: T::from_vec(Vec::new(), dimensions.x.max(dimensions.y)),
             dimensions,
             gravity,
-            step: STEP,
+            force_fields: Vec::new(),
+            next_field_id: 0,
+            pointers: BTreeMap::new(),
             mouse_pos: None,
-            show_quad_tree: false,
             is_pressing_mouse: false,
+            tool: Tool::Attract,
+            tool_radius: TOOL_RADIUS,
+            paint: Paint::Color(Rgba::WHITE),
+            time: 0.,
+            params: SimulationParams::default(),
+            stats: SolverStats::default(),
+            rng: Rng::new(seed),
+            rest_number_density: sph::lattice_number_density(PARTICLE_RADIUS),
+        }
+    }
+
+    /// Moves the whole world, pointers and force fields included, to another spatial index.
+    pub fn with_tree<U: GeoQuery<Particle>>(self) -> World<U> {
+        let mut world = World {
+            particles: self.particles,
+            channels: self.channels,
+            phases: self.phases,
+            obstacles: self.obstacles,
+            emitters: self.emitters,
+            sinks: self.sinks,
+            boundary: self.boundary,
+            boundary_outdated: self.boundary_outdated,
+            sdf: self.sdf,
+            tree: U::from_vec(Vec::new(), self.dimensions.x.max(self.dimensions.y)),
+            dimensions: self.dimensions,
+            gravity: self.gravity,
+            force_fields: self.force_fields,
+            next_field_id: self.next_field_id,
+            pointers: self.pointers,
+            mouse_pos: self.mouse_pos,
+            is_pressing_mouse: self.is_pressing_mouse,
+            tool: self.tool,
+            tool_radius: self.tool_radius,
+            paint: self.paint,
+            time: self.time,
+            params: self.params,
+            stats: self.stats,
+            rng: self.rng,
+            rest_number_density: self.rest_number_density,
+        };
+        world.update_tree();
+        world
+    }
+
+    /// Replaces the parameters, resampling the boundary as the walls may have changed.
+    pub fn set_params(&mut self, params: SimulationParams) {
+        self.params = params;
+        self.boundary_outdated = true;
+    }
+
+    /// Adds a force field and returns the id to remove it with.
+    pub fn add_force_field(&mut self, field: Box<dyn ForceField>) -> u32 {
+        let id = self.next_field_id;
+        self.next_field_id += 1;
+        self.force_fields.push((id, field));
+        id
+    }
+
+    /// Removes a force field, returns false when there is no field with that id or when
+    /// it belongs to a pointer.
+    pub fn remove_force_field(&mut self, id: u32) -> bool {
+        if self.pointer_fields().contains(&id) {
+            return false;
         }
+        let len = self.force_fields.len();
+        self.force_fields.retain(|(field_id, _)| *field_id != id);
+        self.force_fields.len() != len
     }
 
-    pub fn update_mouse_pos(&mut self, mouse_pos: Option<V2>, is_pressing: bool) {
-        self.mouse_pos = mouse_pos;
-        self.is_pressing_mouse = is_pressing;
+    /// The force fields that can be saved with their id, leaving out the fields of the
+    /// pointers' tools.
+    pub(super) fn saved_force_fields(&self) -> Vec<(u32, FieldSpec)> {
+        let pointer_fields = self.pointer_fields();
+        self.force_fields
+            .iter()
+            .filter(|(id, _)| !pointer_fields.contains(id))
+            .filter_map(|(id, field)| Some((*id, field.spec()?)))
+            .collect()
     }
 
-    pub fn add_random_particles(&mut self, n: usize, rng: impl Fn() -> f64) {
+    /// Removes every force field but the ones of the pointers.
+    pub fn clear_force_fields(&mut self) {
+        let pointer_fields = self.pointer_fields();
+        self.force_fields
+            .retain(|(id, _)| pointer_fields.contains(id));
+    }
+
+    pub fn add_random_particles(&mut self, n: usize) {
         for _ in 0..n {
-            let x = rng() * self.dimensions.x;
-            let y = rng() * self.dimensions.y;
+            let x = self.rng.next_f64() * self.dimensions.x;
+            let y = self.rng.next_f64() * self.dimensions.y;
             let vx = 0.0;
             let vy = 0.0;
             let particle = Particle::new(V2::new(x, y), V2::new(vx, vy));
-            self.particles.push(particle);
+            self.push_particle(particle);
+        }
+        self.update_tree();
+    }
+
+    /// Adds a fluid particle of the default phase and returns its index.
+    pub fn add_particle(&mut self, position: V2, velocity: V2) -> usize {
+        self.push_particle(Particle::new(position, velocity));
+        self.update_tree();
+        self.particles.len() - 1
+    }
+
+    /// Fills a shape with particles at rest on a square lattice of the given spacing,
+    /// skipping points outside the domain or inside solids. Returns how many were added,
+    /// none for a spacing below `MIN_SPACING`.
+    pub fn add_particles_in_shape(&mut self, shape: &impl Shape, spacing: f64) -> usize {
+        if spacing.is_nan() || spacing < MIN_SPACING {
+            return 0;
         }
+        let bounds = shape.bounding_box();
+        let columns = (bounds.width() / spacing).floor() as usize;
+        let rows = (bounds.height() / spacing).floor() as usize;
+        let positions: Vec<V2> = (0..=rows)
+            .flat_map(|j| (0..=columns).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                V2::new(
+                    bounds.x0 + i as f64 * spacing,
+                    bounds.y0 + j as f64 * spacing,
+                )
+            })
+            .filter(|p| shape.contains((p.x, p.y).into()) && self.is_free(p))
+            .collect();
+        let added = positions.len();
+        positions
+            .into_iter()
+            .for_each(|position| self.push_particle(Particle::new(position, V2::new(0., 0.))));
+        self.update_tree();
+        added
+    }
+
+    /// Removes the particles within `radius` of `center` and returns how many were removed.
+    pub fn remove_particles_in_radius(&mut self, center: &V2, radius: f64) -> usize {
+        let len = self.particles.len();
+        self.particles
+            .retain(|particle| particle.position.distance_to(center) > radius);
+        self.update_tree();
+        len - self.particles.len()
+    }
+
+    /// Removes every particle, keeping the rest of the scene.
+    pub fn clear(&mut self) {
+        self.particles.clear();
         self.update_tree();
     }
 
+    /// Adds a particle making sure it carries a value for every registered channel.
+    pub(super) fn push_particle(&mut self, mut particle: Particle) {
+        particle.channels.resize(self.channels.len(), 0.);
+        self.particles.push(particle);
+    }
+
+    /// Registers a custom scalar channel, initialized to 0 on every particle,
+    /// and returns its index in `Particle::channels`.
+    pub fn add_channel(&mut self, name: &str) -> usize {
+        let index = self.channels.register(name);
+        let len = self.channels.len();
+        self.particles
+            .iter_mut()
+            .for_each(|particle| particle.channels.resize(len, 0.));
+        index
+    }
+
+    pub fn phase_of(&self, particle: &Particle) -> &Phase {
+        self.phases
+            .get(particle.phase as usize)
+            .unwrap_or(&self.phases[0])
+    }
+
+    /// Registers a new fluid and returns its phase id.
+    pub fn add_phase(&mut self, phase: Phase) -> u32 {
+        self.phases.push(phase);
+        (self.phases.len() - 1) as u32
+    }
+
+    /// Moves a particle to another fluid, taking over its mass and colour.
+    pub fn set_particle_phase(&mut self, index: usize, phase: u32) {
+        let Some(Phase { density, color, .. }) = self.phases.get(phase as usize).cloned() else {
+            return;
+        };
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.phase = phase;
+            particle.mass = density;
+            particle.color = color;
+        }
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) -> usize {
+        self.obstacles.push(obstacle);
+        self.boundary_outdated = true;
+        self.obstacles.len() - 1
+    }
+
+    pub fn remove_obstacle(&mut self, index: usize) {
+        if index < self.obstacles.len() {
+            self.obstacles.remove(index);
+            self.boundary_outdated = true;
+        }
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles.clear();
+        self.boundary_outdated = true;
+    }
+
+    /// Contribution of a neighbour to number density sums, boundary particles
+    /// count as their corrected volume of rest fluid.
+    pub(super) fn neighbour_weight(&self, other: &Particle) -> f64 {
+        match other.kind {
+            ParticleKind::Fluid => 1.,
+            ParticleKind::Boundary { volume } => self.rest_number_density * volume,
+        }
+    }
+
     pub fn calc_force(&self, particle: &Particle) -> V2 {
+        let viscosity = self.phase_of(particle).viscosity;
         let mut gradient = V2::new(0., 0.);
         let point = &particle.position;
         self.tree.query_distance(point, PARTICLE_RADIUS, |other| {
             let p_vec = other.position.sub(&particle.position);
             let p_norm = p_vec.normalized();
             let d = p_vec.len();
-            if d > PARTICLE_RADIUS || d < 0.001 {
+            if !(0.001..=PARTICLE_RADIUS).contains(&d) {
                 return;
             }
             let kernel = smoothing_kernel_gradient(d);
-            let g = -kernel * PRESSURE_MULTIPLIER;
+            let weight = self.neighbour_weight(other);
+            let g = -kernel * PRESSURE_MULTIPLIER * weight;
             gradient = gradient + g * p_norm;
-            let friction_particle = -FRICTION * particle.velocity.sub(&other.velocity);
+            let friction = FRICTION * weight * (viscosity + self.phase_of(other).viscosity) / 2.;
+            let friction_particle = -friction * particle.velocity.sub(&other.velocity);
             // let velocity_direction = particle.velocity.normalized();
             // let collision_penalty = -1. * kernel * velocity_direction;
             gradient = gradient + friction_particle;
         });
-        gradient
+        gradient / particle.mass
     }
 
-    pub fn calc_particle_acc(&self, particle: &Particle) -> V2 {
-        let acc = self.calc_force(&particle);
-        if let Some(ref mouse_pos) = self.mouse_pos {
-            if self.is_pressing_mouse {
-                let mouse_distance = mouse_pos.sub(&particle.position);
-                let l = mouse_distance.len();
-                if l > 100. {
-                    return acc;
+    pub(super) fn compute_number_densities(&mut self, neighbours: &[Vec<Neighbour>]) {
+        let densities: Vec<f64> = self
+            .particles
+            .iter()
+            .zip(neighbours)
+            .map(|(p, neighbours)| {
+                neighbours.iter().fold(0., |number_density, neighbour| {
+                    let other = self.neighbour(neighbour);
+                    let d = p.position.distance_to(&self.neighbour_position(neighbour));
+                    number_density + self.neighbour_weight(other) * sph::poly6(d)
+                })
+            })
+            .collect();
+        self.particles
+            .iter_mut()
+            .zip(densities)
+            .for_each(|(particle, density)| particle.sph.number_density = density);
+    }
+
+    /// Surface normals pointing out of the fluid, as the curvature term expects.
+    pub(super) fn compute_normals(&mut self, neighbours: &[Vec<Neighbour>]) {
+        let normals: Vec<V2> = self
+            .particles
+            .iter()
+            .zip(neighbours)
+            .map(|(p, neighbours)| {
+                let normal = neighbours
+                    .iter()
+                    .filter(|neighbour| !self.neighbour(neighbour).is_boundary())
+                    .fold(V2::new(0., 0.), |normal, neighbour| {
+                        let r = p.position.sub(&self.neighbour_position(neighbour));
+                        let density = self.neighbour(neighbour).sph.number_density;
+                        normal.sub(&(sph::spiky_gradient(&r) / density))
+                    });
+                SMOOTHING_RADIUS * normal
+            })
+            .collect();
+        self.particles
+            .iter_mut()
+            .zip(normals)
+            .for_each(|(particle, normal)| particle.sph.normal = normal);
+    }
+
+    /// Akinci et al. 2013 surface tension: a cohesion term pulling neighbours together
+    /// and a curvature term that minimizes the free surface.
+    pub fn calc_surface_tension(&self, particle: &Particle) -> V2 {
+        let mut acc = V2::new(0., 0.);
+        let gamma = self.params.surface_tension;
+        self.tree
+            .query_distance(&particle.position, SMOOTHING_RADIUS, |other| {
+                let r = particle.position.sub(&other.position);
+                let d = r.len();
+                if other.is_boundary() || !(0.001..SMOOTHING_RADIUS).contains(&d) {
+                    return;
                 }
-                let mouse_acc = mouse_distance.normalized().scalar_mul(-MOUSE_FORCE);
-                return acc.add(&mouse_acc);
+                let other = self.source(other);
+                let correction = 2. * self.rest_number_density
+                    / (particle.sph.number_density + other.sph.number_density);
+                let cohesion = -gamma * other.mass * sph::cohesion(d) * r.normalized();
+                let curvature = -gamma * particle.sph.normal.sub(&other.sph.normal);
+                acc = acc + correction * (cohesion + curvature);
+            });
+        acc
+    }
+
+    pub fn calc_particle_acc(&self, particle: &Particle) -> V2 {
+        let acc = self.calc_force(particle);
+        acc + self.calc_external_acc(particle)
+    }
+
+    /// Accelerations that do not come from the pressure model, shared by all solvers.
+    pub fn calc_external_acc(&self, particle: &Particle) -> V2 {
+        let mut acc = self.gravity.acceleration(&particle.position)
+            + self.calc_sdf_acc(particle)
+            + self.calc_grab_acc(particle);
+        if self.params.surface_tension > 0. {
+            acc = acc + self.calc_surface_tension(particle);
+        }
+        self.force_fields.iter().fold(acc, |acc, (_, field)| {
+            acc + field.acceleration(particle, self.time)
+        })
+    }
+
+    pub(super) fn update_tree(&mut self) {
+        self.particles
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, particle)| particle.slot = i);
+        let mut values = self.particles.clone();
+        values.extend(self.periodic_ghosts());
+        if self.params.boundary_particles {
+            if self.boundary_outdated {
+                self.boundary = self.sample_boundary();
+                self.boundary
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(i, particle)| particle.slot = i);
+                self.boundary_outdated = false;
             }
+            values.extend(self.boundary.iter().cloned());
         }
-        return acc;
+        self.tree = T::from_vec(values, self.dimensions.x.max(self.dimensions.y));
     }
 
-    fn update_tree(&mut self) {
-        self.tree = T::from_vec(
-            self.particles.clone(),
-            self.dimensions.x.max(self.dimensions.y),
-        );
+    fn evolve_once(&mut self) {
+        self.apply_tools();
+        self.apply_emitters_and_sinks();
+        match self.params.solver {
+            Solver::Force => self._evolve(),
+            Solver::PositionBased => self.pbf_step(),
+            Solver::Incompressible => self.dfsph_step(),
+        }
+        self.remove_escaped();
+        self.time += self.params.dt;
     }
 
-    pub fn evolve(&mut self, n: usize) {
+    /// Advances `n` steps, `stats` then reports the solver work of these steps. Stops at
+    /// the first step that leaves a particle with a non-finite position or velocity and
+    /// puts the world back as it was before the call.
+    ///
+    /// The rollback costs a copy of the particles per call, not per step.
+    pub fn checked_evolve(&mut self, n: usize) -> Result<(), FluidError> {
+        let before = StepBackup::of(self);
+        self.stats = SolverStats::default();
         for _ in 0..n {
-            self._evolve();
+            self.evolve_once();
+            if let Some(particle) = self.particles.iter().position(|p| !p.is_finite()) {
+                let error = FluidError::Unstable {
+                    time: self.time - before.time,
+                    particle,
+                };
+                before.restore(self);
+                return Err(error);
+            }
         }
+        Ok(())
     }
 
     fn _evolve(&mut self) {
-        let dt = self.step;
+        let dt = self.params.dt;
         self.update_tree();
+        // the penalty forces don't need them, but densities are part of the output
+        let neighbours = self.find_neighbours();
+        self.compute_number_densities(&neighbours);
+        if self.params.surface_tension > 0. {
+            self.compute_normals(&neighbours);
+        }
         self.particles = self
             .particles
             .iter()
             .map(|p| {
-                let acc = self.calc_particle_acc(&p) + self.gravity;
+                let acc = self.calc_particle_acc(p);
                 let mut particle = p.rk4_integrate(acc, dt);
                 particle.velocity = particle.velocity * (0.999); //so that they loose energy
+                self.resolve_collisions(&mut particle);
+                particle
+            })
+            .collect();
+    }
 
-                if particle.position.x < 0. {
-                    particle.position.x = 0.;
-                    particle.velocity.x = -particle.velocity.x;
-                }
+    pub(super) fn resolve_collisions(&self, particle: &mut Particle) {
+        self.obstacles
+            .iter()
+            .for_each(|obstacle| obstacle.resolve(particle));
+        self.resolve_sdf(particle);
+        self.resolve_walls(particle);
+    }
+}
 
-                if particle.position.x > self.dimensions.x {
-                    particle.position.x = self.dimensions.x;
-                    particle.velocity.x = -particle.velocity.x;
-                }
+/// What a step changes, the rest of the world, like pointers, force fields and
+/// obstacles, only changes between calls.
+struct StepBackup {
+    particles: Vec<Particle>,
+    time: f64,
+    stats: SolverStats,
+    rng: Rng,
+    /// `Emitter::pending` of every emitter.
+    pending: Vec<f64>,
+}
 
-                if particle.position.y < 0. {
-                    particle.position.y = 0.;
-                    particle.velocity.y = -particle.velocity.y;
-                }
+impl StepBackup {
+    fn of<T>(world: &World<T>) -> StepBackup {
+        StepBackup {
+            particles: world.particles.clone(),
+            time: world.time,
+            stats: world.stats,
+            rng: world.rng.clone(),
+            pending: world.emitters.iter().map(|e| e.pending).collect(),
+        }
+    }
 
-                if particle.position.y > self.dimensions.y {
-                    particle.position.y = self.dimensions.y;
-                    particle.velocity.y = -particle.velocity.y;
-                }
-                return particle;
-            })
-            .collect();
+    fn restore<T: GeoQuery<Particle>>(self, world: &mut World<T>) {
+        world.particles = self.particles;
+        world.time = self.time;
+        world.stats = self.stats;
+        world.rng = self.rng;
+        world
+            .emitters
+            .iter_mut()
+            .zip(self.pending)
+            .for_each(|(emitter, pending)| emitter.pending = pending);
+        world.update_tree();
     }
 }
 
@@ -177,12 +630,85 @@ pub trait GeoQuery<T> {
 
 impl ParticleLike for Particle {
     fn with_position_and_velocity(&self, position: V2, velocity: V2) -> Particle {
-        Particle { position, velocity }
+        Particle {
+            position,
+            velocity,
+            ..self.clone()
+        }
     }
     fn position(&self) -> V2 {
-        self.position.clone()
+        self.position
     }
     fn velocity(&self) -> V2 {
-        self.velocity.clone()
+        self.velocity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::hash_grid::HashGrid;
+
+    #[test]
+    fn blocks_fill_a_lattice_and_stay_queryable() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(100., 100.), Gravity::Zero, 0);
+        let block = kurbo::Rect::new(10., 10., 30., 30.);
+        assert_eq!(world.add_particles_in_shape(&block, PARTICLE_RADIUS), 25);
+        let mut found = 0;
+        world
+            .tree
+            .query_distance(&V2::new(20., 20.), 100., |_| found += 1);
+        assert_eq!(found, 25);
+        assert_eq!(world.remove_particles_in_radius(&V2::new(10., 10.), 0.5), 1);
+        world.clear();
+        let mut found = 0;
+        world
+            .tree
+            .query_distance(&V2::new(20., 20.), 100., |_| found += 1);
+        assert_eq!(found, 0);
+    }
+
+    #[test]
+    fn surface_tension_pulls_neighbours_together() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(100., 100.), Gravity::Zero, 0);
+        world.add_particle(V2::new(47., 50.), V2::new(0., 0.));
+        world.add_particle(V2::new(53., 50.), V2::new(0., 0.));
+        world.update_tree();
+        let neighbours = world.find_neighbours();
+        world.compute_number_densities(&neighbours);
+        world.compute_normals(&neighbours);
+        let left = world.particles[0].clone();
+        assert_eq!(world.calc_external_acc(&left), V2::new(0., 0.));
+
+        world.params.surface_tension = 1.;
+        let acc = world.calc_external_acc(&left);
+        assert!(acc.x > 0.);
+        assert!(acc.y.abs() < 1e-9);
+        let right = world.particles[1].clone();
+        assert!(world.calc_external_acc(&right).x < 0.);
+    }
+
+    #[test]
+    fn every_solver_computes_densities() {
+        [Solver::Force, Solver::PositionBased, Solver::Incompressible]
+            .into_iter()
+            .for_each(|solver| {
+                let mut world =
+                    World::<HashGrid<Particle>>::new(V2::new(100., 100.), Gravity::Zero, 0);
+                world.params.solver = solver;
+                let block = kurbo::Rect::new(30., 30., 70., 70.);
+                world.add_particles_in_shape(&block, PARTICLE_RADIUS);
+                world.checked_evolve(1).unwrap();
+                let center = world
+                    .particles
+                    .iter()
+                    .min_by(|a, b| {
+                        let d = |p: &Particle| p.position.distance_to(&V2::new(50., 50.));
+                        d(a).total_cmp(&d(b))
+                    })
+                    .unwrap();
+                let relative = center.sph.number_density / world.rest_number_density;
+                assert!((relative - 1.).abs() < 0.1, "{solver:?}: {relative}");
+            });
     }
 }
@@ -0,0 +1,156 @@
+use super::{
+    neighbours::Neighbour,
+    particle::{GeoQuery, Particle, World},
+    sph::{self, SMOOTHING_RADIUS},
+    v2::V2,
+};
+
+/// Constraint force mixing term, keeps the lambda denominator away from 0.
+const RELAXATION: f64 = 0.01;
+/// Artificial pressure (tensile instability correction) constants.
+const TENSILE_K: f64 = 0.1;
+const TENSILE_DQ: f64 = 0.2 * SMOOTHING_RADIUS;
+const TENSILE_N: i32 = 4;
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// One step of Position Based Fluids (Macklin & Müller 2013). Neighbours are found
+    /// once after the prediction and kept for all the constraint iterations.
+    pub(super) fn pbf_step(&mut self) {
+        let dt = self.params.dt;
+        self.update_tree();
+        if self.params.surface_tension > 0. {
+            let neighbours = self.find_neighbours();
+            self.compute_number_densities(&neighbours);
+            self.compute_normals(&neighbours);
+        }
+        let previous_positions: Vec<V2> = self.particles.iter().map(|p| p.position).collect();
+        self.particles = self
+            .particles
+            .iter()
+            .map(|p| {
+                let mut particle = p.clone();
+                particle.velocity = particle.velocity + dt * self.calc_external_acc(p);
+                particle.position = particle.position + dt * particle.velocity;
+                self.resolve_collisions(&mut particle);
+                particle
+            })
+            .collect();
+
+        self.update_tree();
+        let neighbours = self.find_neighbours();
+        let mut lambdas = vec![0.; self.particles.len()];
+        for _ in 0..self.params.pbf_iterations {
+            self.compute_lambdas(&neighbours, &mut lambdas);
+            self.apply_position_corrections(&neighbours, &lambdas);
+        }
+
+        let velocities: Vec<V2> = self
+            .particles
+            .iter()
+            .zip(previous_positions)
+            .map(|(particle, previous)| self.periodic_offset(particle.position.sub(&previous)) / dt)
+            .collect();
+        self.particles
+            .iter_mut()
+            .zip(velocities)
+            .for_each(|(particle, velocity)| particle.velocity = velocity);
+        self.apply_xsph_viscosity(&neighbours);
+    }
+
+    /// Density constraints are expressed on the number density, and the corrections are
+    /// weighted by the inverse mass so heavier phases sink below lighter ones.
+    fn compute_lambdas(&mut self, neighbours: &[Vec<Neighbour>], lambdas: &mut [f64]) {
+        let rest_number_density = self.rest_number_density;
+        let densities: Vec<f64> = self
+            .particles
+            .iter()
+            .zip(neighbours)
+            .zip(lambdas.iter_mut())
+            .map(|((p, neighbours), lambda)| {
+                let mut number_density = 0.;
+                let mut gradient_i = V2::new(0., 0.);
+                let mut gradient_sum = 0.;
+                neighbours.iter().for_each(|neighbour| {
+                    let other = self.neighbour(neighbour);
+                    let r = p.position.sub(&self.neighbour_position(neighbour));
+                    let weight = self.neighbour_weight(other);
+                    number_density += weight * sph::poly6(r.len());
+                    let gradient = (weight / rest_number_density) * sph::spiky_gradient(&r);
+                    gradient_i = gradient_i + gradient;
+                    if !other.is_boundary() {
+                        gradient_sum += gradient.norm_sqr() / other.mass;
+                    }
+                });
+                gradient_sum += gradient_i.norm_sqr() / p.mass;
+                let constraint = (number_density / rest_number_density - 1.).max(0.);
+                *lambda = -constraint / (gradient_sum + RELAXATION);
+                number_density
+            })
+            .collect();
+        self.particles
+            .iter_mut()
+            .zip(densities)
+            .for_each(|(particle, density)| particle.sph.number_density = density);
+    }
+
+    fn apply_position_corrections(&mut self, neighbours: &[Vec<Neighbour>], lambdas: &[f64]) {
+        let rest_number_density = self.rest_number_density;
+        let tensile_reference = sph::poly6(TENSILE_DQ);
+        let corrections: Vec<V2> = self
+            .particles
+            .iter()
+            .zip(neighbours)
+            .zip(lambdas)
+            .map(|((p, neighbours), lambda_i)| {
+                let delta = neighbours.iter().fold(V2::new(0., 0.), |delta, neighbour| {
+                    let other = self.neighbour(neighbour);
+                    let r = p.position.sub(&self.neighbour_position(neighbour));
+                    let s_corr =
+                        -TENSILE_K * (sph::poly6(r.len()) / tensile_reference).powi(TENSILE_N);
+                    let lambda_j = neighbour.fluid_index().map_or(0., |j| lambdas[j]);
+                    let lambda = lambda_i + lambda_j + s_corr;
+                    delta + (self.neighbour_weight(other) * lambda) * sph::spiky_gradient(&r)
+                });
+                delta / (p.mass * rest_number_density)
+            })
+            .collect();
+        let mut particles = std::mem::take(&mut self.particles);
+        particles
+            .iter_mut()
+            .zip(corrections)
+            .for_each(|(particle, correction)| {
+                particle.position = particle.position + correction;
+                self.resolve_collisions(particle);
+            });
+        self.particles = particles;
+    }
+
+    pub(super) fn apply_xsph_viscosity(&mut self, neighbours: &[Vec<Neighbour>]) {
+        let c = self.params.xsph_viscosity;
+        let corrections: Vec<V2> = self
+            .particles
+            .iter()
+            .zip(neighbours)
+            .map(|(p, neighbours)| {
+                let viscosity = self.phase_of(p).viscosity;
+                neighbours
+                    .iter()
+                    .filter(|neighbour| neighbour.fluid_index().is_some())
+                    .fold(V2::new(0., 0.), |correction, neighbour| {
+                        let other = self.neighbour(neighbour);
+                        let d = p.position.distance_to(&self.neighbour_position(neighbour));
+                        let w = sph::poly6(d);
+                        let mixed = (viscosity + self.phase_of(other).viscosity) / 2.;
+                        let weight = mixed * w / self.rest_number_density;
+                        correction + weight * other.velocity.sub(&p.velocity)
+                    })
+            })
+            .collect();
+        self.particles
+            .iter_mut()
+            .zip(corrections)
+            .for_each(|(particle, correction)| {
+                particle.velocity = particle.velocity + c * correction
+            });
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+use super::attributes::Rgba;
+use crate::error::{non_negative, positive, FluidError};
+
+/// An immiscible fluid. Particles of a phase get `density` as their mass, so that
+/// with the number density formulation heavier fluids sink below lighter ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Phase {
+    /// Rest density relative to the default fluid.
+    pub density: f64,
+    /// Viscosity relative to the default fluid.
+    pub viscosity: f64,
+    pub color: Rgba,
+}
+
+impl Phase {
+    pub(crate) fn validate(&self) -> Result<(), FluidError> {
+        positive("density", self.density)?;
+        non_negative("viscosity", self.viscosity)
+    }
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Phase {
+            density: 1.,
+            viscosity: 1.,
+            color: Rgba::WHITE,
+        }
+    }
+}
+
+/// Checks that `phase` is one of the `count` registered phases.
+pub(crate) fn registered(phase: u32, count: usize) -> Result<(), FluidError> {
+    if (phase as usize) < count {
+        Ok(())
+    } else {
+        Err(FluidError::InvalidArgument {
+            name: "phase",
+            reason: "must be a registered phase",
+        })
+    }
+}
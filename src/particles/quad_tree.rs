@@ -49,10 +49,6 @@ impl<T: TreeValue> QuadTree<T> {
         });
     }
 
-    pub fn get_circ(&self) -> Circle {
-        self.circ
-    }
-
     pub fn get_rect(&self) -> Rect {
         Rect::new(
             self.center.x - self.half_width,
@@ -62,11 +58,11 @@ impl<T: TreeValue> QuadTree<T> {
         )
     }
 
-    pub fn for_each(&self, f: impl Fn(&QuadTree<T>)) {
-        self._for_each(&f);
+    pub fn for_each(&self, mut f: impl FnMut(&QuadTree<T>)) {
+        self._for_each(&mut f);
     }
 
-    fn _for_each(&self, f: &impl Fn(&QuadTree<T>)) {
+    fn _for_each(&self, f: &mut impl FnMut(&QuadTree<T>)) {
         f(self);
         match &self.node {
             QuadTreeNode::Empty => {}
@@ -89,8 +85,7 @@ impl<T: TreeValue> QuadTree<T> {
         self.node = match node {
             QuadTreeNode::Empty => QuadTreeNode::Leaf { value },
             QuadTreeNode::Leaf { value: this_value } => {
-                let mut other =
-                    QuadTree::new_node(self.center.clone(), self.half_width, self.half_height);
+                let mut other = QuadTree::new_node(self.center, self.half_width, self.half_height);
                 if value.position().sub(&this_value.position()).len() < 0.001 {
                     value.offset_pos();
                     return;
@@ -165,7 +160,6 @@ impl<T: TreeValue> QuadTree<T> {
         let circle = Circle::new((point.x, point.y), r);
         if !circles_intersect(&self.circ, &circle) {
             return vec;
-        } else {
         }
         vec.push(self);
         // rect.bounding_box()
@@ -227,7 +221,7 @@ mod tests {
 
     impl TreeValue for V2 {
         fn position(&self) -> V2 {
-            self.clone()
+            *self
         }
         fn offset_pos(&mut self) {
             *self = self.add(&V2::new(0.0001, 0.0001));
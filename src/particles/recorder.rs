@@ -0,0 +1,160 @@
+use crate::snapshot::{Decode, Encode, Reader, SnapshotError, SnapshotKind, Writer};
+
+use super::tools::PointerState;
+
+/// A call to `CanvasDriven` that changes how the simulation unfolds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Input {
+    MousePos { x: f64, y: f64, is_pressing: bool },
+    RemoveMousePos,
+    Evolve(usize),
+}
+
+/// An input and the simulation time it was given at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Event {
+    pub time: f64,
+    pub input: Input,
+}
+
+/// A session: the snapshot the recording started from, the pointers pressed at that
+/// time and the inputs given after it.
+pub(super) struct Recording {
+    pub snapshot: Vec<u8>,
+    pub pointers: PointerState,
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    pub fn new(snapshot: Vec<u8>, pointers: PointerState) -> Recording {
+        Recording {
+            snapshot,
+            pointers,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, time: f64, input: Input) {
+        self.events.push(Event { time, input });
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(SnapshotKind::Recording);
+        writer.put(&self.snapshot);
+        writer.put(&self.pointers);
+        writer.put(&self.events);
+        writer.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Recording, SnapshotError> {
+        let mut reader = Reader::new(bytes, SnapshotKind::Recording)?;
+        let recording = Recording {
+            snapshot: reader.get()?,
+            pointers: reader.get()?,
+            events: reader.get()?,
+        };
+        reader.finish()?;
+        Ok(recording)
+    }
+}
+
+impl Encode for Event {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.time);
+        match self.input {
+            Input::MousePos { x, y, is_pressing } => {
+                writer.put(&0u8);
+                writer.put(&x);
+                writer.put(&y);
+                writer.put(&is_pressing);
+            }
+            Input::RemoveMousePos => writer.put(&1u8),
+            Input::Evolve(n) => {
+                writer.put(&2u8);
+                writer.put(&n);
+            }
+        }
+    }
+}
+
+impl Decode for Event {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        let time = reader.get()?;
+        let input = match reader.get::<u8>()? {
+            0 => Input::MousePos {
+                x: reader.get()?,
+                y: reader.get()?,
+                is_pressing: reader.get()?,
+            },
+            1 => Input::RemoveMousePos,
+            2 => Input::Evolve(reader.get()?),
+            _ => return Err(SnapshotError::Invalid("recorded input")),
+        };
+        Ok(Event { time, input })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{CanvasDriven, CanvasDrivenArgs, Tool, TreeType};
+
+    #[test]
+    fn replay_reproduces_the_session() {
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            tree_type: TreeType::Quad,
+            particles: 150,
+            seed: Some(3),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        canvas.evolve(10).unwrap();
+        canvas.start_recording();
+        canvas.update_mouse_pos(300., 300., true).unwrap();
+        canvas.evolve(5).unwrap();
+        canvas.update_mouse_pos(420., 280., true).unwrap();
+        canvas.evolve(5).unwrap();
+        canvas.remove_mouse_pos();
+        canvas.evolve(5).unwrap();
+        let log = canvas.stop_recording().unwrap();
+
+        let mut replayed = CanvasDriven::new(CanvasDrivenArgs {
+            seed: Some(1),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        replayed.play(&log).unwrap();
+        assert_eq!(replayed.replay_until(f64::INFINITY), Ok(0));
+        assert_eq!(replayed.save_snapshot(), canvas.save_snapshot());
+    }
+
+    #[test]
+    fn replay_starts_with_the_mouse_held_at_the_start() {
+        [Tool::Attract, Tool::Drag].into_iter().for_each(|tool| {
+            let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+                particles: 150,
+                seed: Some(4),
+                ..CanvasDrivenArgs::default()
+            })
+            .unwrap();
+            canvas.add_drag_field(0.1).unwrap();
+            canvas.set_tool(tool);
+            canvas.update_mouse_pos(300., 300., true).unwrap();
+            canvas.evolve(5).unwrap();
+            canvas.start_recording();
+            canvas.evolve(5).unwrap();
+            canvas.update_mouse_pos(380., 320., true).unwrap();
+            canvas.evolve(5).unwrap();
+            let log = canvas.stop_recording().unwrap();
+
+            let mut replayed = CanvasDriven::new(CanvasDrivenArgs {
+                seed: Some(1),
+                ..CanvasDrivenArgs::default()
+            })
+            .unwrap();
+            replayed.play(&log).unwrap();
+            assert_eq!(replayed.pointer_count(), 1);
+            assert_eq!(replayed.replay_until(f64::INFINITY), Ok(0));
+            assert_eq!(replayed.save_snapshot(), canvas.save_snapshot());
+        });
+    }
+}
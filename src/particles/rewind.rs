@@ -0,0 +1,282 @@
+use std::collections::VecDeque;
+
+/// Number of frames between two full snapshots, bounds the work to rebuild a frame.
+const KEYFRAME_INTERVAL: usize = 30;
+
+enum FrameData {
+    /// A whole snapshot.
+    Key(Vec<u8>),
+    /// The snapshot XORed with the one of the previous frame, run length encoded.
+    Delta { len: usize, runs: Vec<u8> },
+}
+
+struct Frame {
+    time: f64,
+    data: FrameData,
+}
+
+/// Snapshots of the last `duration` seconds of simulation, to scrub through them,
+/// within `max_bytes` of memory.
+pub(super) struct History {
+    duration: f64,
+    max_bytes: usize,
+    frames: VecDeque<Frame>,
+    /// Snapshot of the newest frame, the base of the next delta.
+    last: Vec<u8>,
+    /// Frame the simulation was moved back to, the following ones are forgotten when
+    /// it continues.
+    position: Option<usize>,
+}
+
+impl History {
+    pub fn new(duration: f64, max_bytes: usize) -> History {
+        History {
+            duration,
+            max_bytes,
+            frames: VecDeque::new(),
+            last: Vec::new(),
+            position: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn time(&self, index: usize) -> Option<f64> {
+        self.frames.get(index).map(|frame| frame.time)
+    }
+
+    /// Bytes used by the stored frames.
+    pub fn memory(&self) -> usize {
+        self.frames
+            .iter()
+            .map(|frame| match &frame.data {
+                FrameData::Key(bytes) => bytes.len(),
+                FrameData::Delta { runs, .. } => runs.len(),
+            })
+            .sum()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+            .unwrap_or_else(|| self.frames.len().saturating_sub(1))
+    }
+
+    /// Moves to a frame and returns its snapshot.
+    pub fn seek(&mut self, index: usize) -> Option<Vec<u8>> {
+        let snapshot = self.snapshot(index)?;
+        self.position = Some(index);
+        Some(snapshot)
+    }
+
+    pub fn push(&mut self, time: f64, snapshot: Vec<u8>) {
+        if let Some(position) = self.position.take() {
+            self.truncate(position);
+        }
+        let since_key = self
+            .frames
+            .iter()
+            .rev()
+            .take_while(|frame| matches!(frame.data, FrameData::Delta { .. }))
+            .count();
+        let data = if self.frames.is_empty() || since_key + 1 >= KEYFRAME_INTERVAL {
+            FrameData::Key(snapshot.clone())
+        } else {
+            FrameData::Delta {
+                len: snapshot.len(),
+                runs: encode_runs(&xor(&self.last, &snapshot, snapshot.len())),
+            }
+        };
+        self.frames.push_back(Frame { time, data });
+        self.last = snapshot;
+        self.forget_old(time);
+        self.forget_over_budget();
+    }
+
+    /// Drops the frames older than the duration, keeping at least one.
+    fn forget_old(&mut self, now: f64) {
+        while self.frames.len() > 1 && now - self.frames[1].time >= self.duration {
+            let first = self.frames.pop_front().map(|frame| frame.data);
+            // the new first frame may be a delta, which needs a base
+            if let (Some(FrameData::Key(base)), Some(frame)) = (first, self.frames.front_mut()) {
+                if let FrameData::Delta { len, runs } = &frame.data {
+                    frame.data = FrameData::Key(xor(&base, &decode_runs(runs, *len), *len));
+                }
+            }
+        }
+    }
+
+    /// Drops the oldest groups of a key frame and its deltas while the frames take more
+    /// than `max_bytes`, keeping the newest group.
+    fn forget_over_budget(&mut self) {
+        while self.memory() > self.max_bytes {
+            let Some(next_key) =
+                (1..self.frames.len()).find(|&i| matches!(self.frames[i].data, FrameData::Key(_)))
+            else {
+                return;
+            };
+            self.frames.drain(..next_key);
+        }
+    }
+
+    /// Rebuilds the snapshot of a frame.
+    fn snapshot(&self, index: usize) -> Option<Vec<u8>> {
+        if index >= self.frames.len() {
+            return None;
+        }
+        let key = (0..=index)
+            .rev()
+            .find(|&i| matches!(self.frames[i].data, FrameData::Key(_)))?;
+        let FrameData::Key(bytes) = &self.frames[key].data else {
+            return None;
+        };
+        let snapshot = self
+            .frames
+            .range(key + 1..=index)
+            .fold(bytes.clone(), |base, frame| match &frame.data {
+                FrameData::Key(bytes) => bytes.clone(),
+                FrameData::Delta { len, runs } => xor(&base, &decode_runs(runs, *len), *len),
+            });
+        Some(snapshot)
+    }
+
+    /// Forgets the frames after `index`, to continue from it on another branch.
+    fn truncate(&mut self, index: usize) {
+        if index + 1 < self.frames.len() {
+            self.last = self.snapshot(index).unwrap_or_default();
+            self.frames.truncate(index + 1);
+        }
+    }
+}
+
+/// XOR of `a` and `b` over `len` bytes, missing bytes are zeros.
+fn xor(a: &[u8], b: &[u8], len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| a.get(i).unwrap_or(&0) ^ b.get(i).unwrap_or(&0))
+        .collect()
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn get_varint(bytes: &mut impl Iterator<Item = u8>) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    for byte in bytes {
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte < 0x80 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+/// Encodes bytes as a sequence of (number of zeros, number of literals, literals),
+/// a delta of two close states being mostly zeros.
+fn encode_runs(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let zeros = bytes[i..].iter().take_while(|&&b| b == 0).count();
+        i += zeros;
+        let literals = bytes[i..].iter().take_while(|&&b| b != 0).count();
+        put_varint(&mut out, zeros);
+        put_varint(&mut out, literals);
+        out.extend_from_slice(&bytes[i..i + literals]);
+        i += literals;
+    }
+    out
+}
+
+fn decode_runs(runs: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len);
+    let mut runs = runs.iter().copied();
+    while out.len() < len {
+        let zeros = get_varint(&mut runs);
+        out.resize(out.len() + zeros, 0);
+        let literals = get_varint(&mut runs);
+        out.extend(runs.by_ref().take(literals));
+        if zeros == 0 && literals == 0 {
+            break;
+        }
+    }
+    out.resize(len, 0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{CanvasDriven, CanvasDrivenArgs};
+    use super::*;
+
+    #[test]
+    fn runs_round_trip() {
+        let bytes = [
+            0, 0, 0, 5, 7, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9,
+        ];
+        assert_eq!(decode_runs(&encode_runs(&bytes), bytes.len()), bytes);
+    }
+
+    #[test]
+    fn seek_restores_past_frames_and_branches() {
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            particles: 100,
+            seed: Some(5),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        canvas.enable_rewind(0.5, usize::MAX).unwrap();
+        let mut snapshots = vec![canvas.save_snapshot()];
+        (0..80).for_each(|_| {
+            canvas.evolve(1).unwrap();
+            snapshots.push(canvas.save_snapshot());
+        });
+        let frames = canvas.rewind_frames();
+        assert!(frames < snapshots.len());
+        let first = snapshots.len() - frames;
+        assert!(canvas.seek(10).unwrap());
+        assert_eq!(canvas.save_snapshot(), snapshots[first + 10]);
+        assert!(canvas.seek(frames - 1).unwrap());
+        assert_eq!(canvas.save_snapshot(), snapshots[snapshots.len() - 1]);
+
+        assert!(canvas.seek(5).unwrap());
+        canvas.set_gravity(0., -30.).unwrap();
+        canvas.evolve(1).unwrap();
+        assert_eq!(canvas.rewind_frames(), 7);
+        assert!(canvas.seek(6).unwrap());
+        assert_ne!(canvas.save_snapshot(), snapshots[first + 6]);
+        assert!(!canvas.seek(7).unwrap());
+    }
+
+    #[test]
+    fn memory_budget_drops_the_oldest_key_frame_groups() {
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            particles: 100,
+            seed: Some(6),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        let snapshot_size = canvas.save_snapshot().len();
+        let budget = 10 * snapshot_size;
+        canvas.enable_rewind(100., budget).unwrap();
+        let mut snapshots = vec![canvas.save_snapshot()];
+        (0..200).for_each(|_| {
+            canvas.evolve(1).unwrap();
+            snapshots.push(canvas.save_snapshot());
+        });
+        assert!(canvas.rewind_memory() <= budget);
+        let frames = canvas.rewind_frames();
+        assert!(frames < snapshots.len());
+        // the oldest frame left is a key frame
+        assert_eq!((snapshots.len() - frames) % KEYFRAME_INTERVAL, 0);
+        assert!(canvas.seek(0).unwrap());
+        assert_eq!(canvas.save_snapshot(), snapshots[snapshots.len() - frames]);
+    }
+}
@@ -6,17 +6,12 @@ use super::{
     v2::{TreeValue, V2},
 };
 
-struct OrderStore<T> {
-    value: T,
-    order: u64,
-}
-
 pub struct RStartree<T: TreeValue> {
     tree: rstar::RTree<MyObj<T>>,
 }
 
 impl<T: TreeValue> RStartree<T> {
-    pub fn from_vec(vec: Vec<T>, order: u64, max_dim: f64) -> Self {
+    pub fn from_vec(vec: Vec<T>, _order: u64, _max_dim: f64) -> Self {
         let objs = vec.into_iter().map(|value| MyObj { value }).collect();
         let star = rstar::RTree::bulk_load(objs);
         RStartree { tree: star }
@@ -54,14 +49,6 @@ impl<T: TreeValue> RStartree<T> {
     }
 }
 
-fn z_order(x: u64, y: u64, order: u64) -> u64 {
-    let mut z = 0;
-    for i in 0..order {
-        z |= ((x & (1 << i)) << i) | ((y & (1 << i)) << (i + 1));
-    }
-    z
-}
-
 impl<T: TreeValue> GeoQuery<T> for RStartree<T> {
     fn query_distance(&self, point: &V2, radius: f64, mut f: impl FnMut(&T)) {
         let rect = Circle::new((point.x, point.y), radius).bounding_box();
@@ -0,0 +1,505 @@
+//! Declarative JSON description of a simulation. Only `width` and `height` are required:
+//!
+//! ```json
+//! {
+//!   "width": 800, "height": 600, "tree_type": "hash_grid", "seed": 1,
+//!   "params": { "solver": "position_based", "bottom_wall": { "mode": "open" } },
+//!   "gravity": { "uniform": [0, 30] },
+//!   "fluid": [{ "shape": { "rect": { "min": [100, 300], "max": [300, 500] } }, "spacing": 4 }],
+//!   "emitters": [{ "shape": { "point": [400, 50] }, "params": { "rate": 200 } }],
+//!   "obstacles": [{ "shape": { "path": "M 300 400 L 500 450 L 300 450 Z" }, "restitution": 0.3 }],
+//!   "force_fields": [{ "vortex": { "center": [400, 300], "radius": 150, "strength": 80, "falloff": 1 } }],
+//!   "pendulums": [{ "balls": 3, "radius": 0.1, "anchor": [400, 100] }]
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::error::{finite, positive, FluidError};
+
+use kurbo::{BezPath, Circle, Point, Rect, Shape};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    emitters::{Emitter, EmitterParams, EmitterShape, Sink},
+    forces::FieldSpec,
+    gravity::Gravity,
+    obstacles::{Obstacle, ObstacleShape},
+    params::SimulationParams,
+    particle::{self, GeoQuery, Particle, World, PARTICLE_RADIUS},
+    phases::{self, Phase},
+    v2::V2,
+    TreeType,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    pub width: f64,
+    pub height: f64,
+    #[serde(default = "default_tree_type")]
+    pub tree_type: TreeType,
+    /// Random when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default = "SimulationParams::default")]
+    pub params: SimulationParams,
+    #[serde(default)]
+    pub gravity: Gravity,
+    /// Particles scattered at random over the domain.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub random_particles: usize,
+    /// Phases added after the default one, which is phase 0.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<Phase>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fluid: Vec<FluidBlock>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub particles: Vec<SceneParticle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emitters: Vec<SceneEmitter>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sinks: Vec<SceneSink>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub obstacles: Vec<SceneObstacle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub force_fields: Vec<FieldSpec>,
+    /// Pendulums are separate simulations, built with `Pendulum::from_scene`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pendulums: Vec<ScenePendulum>,
+}
+
+fn default_tree_type() -> TreeType {
+    TreeType::RStar
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum SceneShape {
+    Circle {
+        center: V2,
+        radius: f64,
+    },
+    Rect {
+        min: V2,
+        max: V2,
+    },
+    /// SVG path data, closed paths are solid.
+    Path(SvgPath),
+}
+
+impl SceneShape {
+    fn validate(&self) -> Result<(), FluidError> {
+        match self {
+            SceneShape::Circle { center, radius } => {
+                finite("center", &[center.x, center.y])?;
+                positive("radius", *radius)
+            }
+            SceneShape::Rect { min, max } => finite("rect", &[min.x, min.y, max.x, max.y]),
+            SceneShape::Path(SvgPath(path)) => {
+                let coordinates: Vec<f64> = path
+                    .elements()
+                    .iter()
+                    .flat_map(|el| el.end_point())
+                    .flat_map(|point| [point.x, point.y])
+                    .collect();
+                finite("path", &coordinates)
+            }
+        }
+    }
+
+    fn to_path(&self) -> BezPath {
+        match self {
+            SceneShape::Circle { center, radius } => {
+                Circle::new((center.x, center.y), *radius).to_path(0.1)
+            }
+            SceneShape::Rect { min, max } => rect(min, max).to_path(0.1),
+            SceneShape::Path(SvgPath(path)) => path.clone(),
+        }
+    }
+}
+
+fn rect(min: &V2, max: &V2) -> Rect {
+    Rect::new(min.x, min.y, max.x, max.y).abs()
+}
+
+fn v2(point: Point) -> V2 {
+    V2::new(point.x, point.y)
+}
+
+#[derive(Clone, Debug)]
+pub struct SvgPath(pub BezPath);
+
+impl Serialize for SvgPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_svg())
+    }
+}
+
+impl<'de> Deserialize<'de> for SvgPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let svg = String::deserialize(deserializer)?;
+        BezPath::from_svg(&svg)
+            .map(SvgPath)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A shape filled with particles at rest.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FluidBlock {
+    pub shape: SceneShape,
+    #[serde(default = "default_spacing")]
+    pub spacing: f64,
+    #[serde(default)]
+    pub phase: u32,
+}
+
+fn default_spacing() -> f64 {
+    PARTICLE_RADIUS
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneParticle {
+    pub position: V2,
+    #[serde(default)]
+    pub velocity: V2,
+    #[serde(default)]
+    pub phase: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneEmitter {
+    pub shape: EmitterShape,
+    #[serde(default = "EmitterParams::default")]
+    pub params: EmitterParams,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum SceneSink {
+    Circle { center: V2, radius: f64 },
+    Rect { min: V2, max: V2 },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneObstacle {
+    pub shape: SceneShape,
+    #[serde(default)]
+    pub restitution: f64,
+    #[serde(default)]
+    pub friction: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenePendulum {
+    pub balls: usize,
+    pub radius: f64,
+    /// Position of the fixed ball on the canvas.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<V2>,
+}
+
+/// A scene that can't be read, with where the problem is.
+#[derive(Debug, PartialEq)]
+pub struct SceneError {
+    /// Path of the faulty value, like `obstacles[1].shape`.
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}, line {} column {}",
+            self.message, self.path, self.line, self.column
+        )
+    }
+}
+
+impl Scene {
+    pub fn parse(json: &str) -> Result<Scene, SceneError> {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let scene = serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+            let path = error.path().to_string();
+            scene_error(path, error.into_inner())
+        })?;
+        deserializer
+            .end()
+            .map_err(|error| scene_error(".".to_string(), error))?;
+        Ok(scene)
+    }
+
+    /// Checks every entry with the same ranges as the `CanvasDriven` methods adding them.
+    pub fn validate(&self) -> Result<(), FluidError> {
+        match self.gravity {
+            Gravity::Uniform(g) => finite("gravity", &[g.x, g.y])?,
+            Gravity::Point { center, strength } => {
+                finite("gravity", &[center.x, center.y, strength])?
+            }
+            Gravity::Zero => {}
+        }
+        self.phases.iter().try_for_each(Phase::validate)?;
+        let phases = self.phases.len() + 1;
+        self.fluid.iter().try_for_each(|block| {
+            block.shape.validate()?;
+            particle::validate_spacing(block.spacing)?;
+            phases::registered(block.phase, phases)
+        })?;
+        self.particles.iter().try_for_each(|p| {
+            let (position, velocity) = (p.position, p.velocity);
+            finite(
+                "particle",
+                &[position.x, position.y, velocity.x, velocity.y],
+            )?;
+            phases::registered(p.phase, phases)
+        })?;
+        self.emitters.iter().try_for_each(|emitter| {
+            emitter.shape.validate()?;
+            emitter.params.validate(phases)
+        })?;
+        self.sinks.iter().try_for_each(|sink| match sink {
+            SceneSink::Circle { center, radius } => {
+                finite("center", &[center.x, center.y])?;
+                positive("radius", *radius)
+            }
+            SceneSink::Rect { min, max } => finite("rect", &[min.x, min.y, max.x, max.y]),
+        })?;
+        self.obstacles.iter().try_for_each(|obstacle| {
+            obstacle.shape.validate()?;
+            finite("response", &[obstacle.restitution, obstacle.friction])
+        })?;
+        self.force_fields.iter().try_for_each(FieldSpec::validate)
+    }
+
+    pub fn to_json(&self) -> String {
+        // scenes have no map with non string keys, the only thing that can fail
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+fn scene_error(path: String, error: serde_json::Error) -> SceneError {
+    let (line, column) = (error.line(), error.column());
+    let message = error.to_string();
+    // serde_json appends the position, which is reported separately
+    let suffix = format!(" at line {line} column {column}");
+    let message = message
+        .strip_suffix(&suffix)
+        .unwrap_or(&message)
+        .to_string();
+    SceneError {
+        path,
+        line,
+        column,
+        message,
+    }
+}
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// Adds what a scene describes, besides the domain, tree type, seed, parameters and
+    /// random particles that the world is created with.
+    pub fn apply_scene(&mut self, scene: &Scene) {
+        self.gravity = scene.gravity;
+        scene.phases.iter().for_each(|phase| {
+            self.add_phase(phase.clone());
+        });
+        scene.fluid.iter().for_each(|block| {
+            let first = self.particles.len();
+            let added = self.add_particles_in_shape(&block.shape.to_path(), block.spacing);
+            (first..first + added).for_each(|i| self.set_particle_phase(i, block.phase));
+        });
+        scene.particles.iter().for_each(|p| {
+            self.push_particle(Particle::new(p.position, p.velocity));
+            self.set_particle_phase(self.particles.len() - 1, p.phase);
+        });
+        scene.emitters.iter().for_each(|emitter| {
+            self.add_emitter(Emitter::new(emitter.shape.clone(), emitter.params));
+        });
+        scene.sinks.iter().for_each(|sink| {
+            self.add_sink(match sink {
+                SceneSink::Circle { center, radius } => {
+                    Sink::Circle(Circle::new((center.x, center.y), *radius))
+                }
+                SceneSink::Rect { min, max } => Sink::Rect(rect(min, max)),
+            });
+        });
+        scene.obstacles.iter().for_each(|obstacle| {
+            let shape = match &obstacle.shape {
+                SceneShape::Circle { center, radius } => {
+                    ObstacleShape::Circle(Circle::new((center.x, center.y), *radius))
+                }
+                SceneShape::Rect { min, max } => ObstacleShape::Rect(rect(min, max)),
+                SceneShape::Path(SvgPath(path)) => ObstacleShape::Path(path.clone()),
+            };
+            self.add_obstacle(Obstacle::new(
+                shape,
+                obstacle.restitution,
+                obstacle.friction,
+            ));
+        });
+        scene.force_fields.iter().for_each(|spec| {
+            self.add_force_field(spec.clone().into_field());
+        });
+        self.update_tree();
+    }
+
+    /// Describes the current state as a scene, with every particle listed. Particle
+    /// colors, channels and the distance field are left out, snapshots keep everything.
+    pub fn to_scene(&self, tree_type: TreeType) -> Scene {
+        Scene {
+            width: self.dimensions.x,
+            height: self.dimensions.y,
+            tree_type,
+            seed: Some(self.rng.state()),
+            params: self.params,
+            gravity: self.gravity,
+            random_particles: 0,
+            phases: self.phases.iter().skip(1).cloned().collect(),
+            fluid: Vec::new(),
+            particles: self
+                .particles
+                .iter()
+                .map(|p| SceneParticle {
+                    position: p.position,
+                    velocity: p.velocity,
+                    phase: p.phase,
+                })
+                .collect(),
+            emitters: self
+                .emitters
+                .iter()
+                .map(|emitter| SceneEmitter {
+                    shape: emitter.shape.clone(),
+                    params: emitter.params,
+                })
+                .collect(),
+            sinks: self
+                .sinks
+                .iter()
+                .map(|sink| match sink {
+                    Sink::Circle(circle) => SceneSink::Circle {
+                        center: v2(circle.center),
+                        radius: circle.radius,
+                    },
+                    Sink::Rect(rect) => SceneSink::Rect {
+                        min: v2(rect.origin()),
+                        max: V2::new(rect.x1, rect.y1),
+                    },
+                })
+                .collect(),
+            obstacles: self
+                .obstacles
+                .iter()
+                .map(|obstacle| SceneObstacle {
+                    shape: match &obstacle.shape {
+                        ObstacleShape::Circle(circle) => SceneShape::Circle {
+                            center: v2(circle.center),
+                            radius: circle.radius,
+                        },
+                        ObstacleShape::Rect(rect) => SceneShape::Rect {
+                            min: v2(rect.origin()),
+                            max: V2::new(rect.x1, rect.y1),
+                        },
+                        ObstacleShape::Path(path) => SceneShape::Path(SvgPath(path.clone())),
+                    },
+                    restitution: obstacle.restitution,
+                    friction: obstacle.friction,
+                })
+                .collect(),
+            force_fields: self
+                .saved_force_fields()
+                .into_iter()
+                .map(|(_, spec)| spec)
+                .collect(),
+            pendulums: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::CanvasDriven;
+    use super::*;
+
+    const SCENE: &str = r#"{
+        "width": 400,
+        "height": 300,
+        "tree_type": "hash_grid",
+        "seed": 3,
+        "params": { "solver": "position_based", "bottom_wall": { "mode": "open" } },
+        "gravity": "zero",
+        "phases": [{ "density": 2, "color": [255, 0, 0, 255] }],
+        "fluid": [{ "shape": { "rect": { "min": [10, 10], "max": [50, 50] } }, "phase": 1 }],
+        "particles": [{ "position": [200, 100], "velocity": [5, 0] }],
+        "emitters": [{ "shape": { "nozzle": { "position": [200, 20], "width": 10 } } }],
+        "sinks": [{ "circle": { "center": [350, 250], "radius": 20 } }],
+        "obstacles": [{ "shape": { "path": "M 100 200 L 200 250 L 100 250 Z" }, "restitution": 0.5 }],
+        "force_fields": [{ "drag": { "coefficient": 0.1 } }]
+    }"#;
+
+    #[test]
+    fn scenes_build_worlds_and_round_trip() {
+        let canvas = CanvasDriven::build(&Scene::parse(SCENE).unwrap()).unwrap();
+        let particles = canvas.world.particles();
+        assert_eq!(particles.len(), 101);
+        assert_eq!(particles.iter().filter(|p| p.phase == 1).count(), 100);
+        assert_eq!(
+            canvas.world.params().bottom_wall.mode,
+            super::super::WallMode::Open
+        );
+
+        let json = canvas.world.to_scene(canvas.tree_type).to_json();
+        let rebuilt = CanvasDriven::build(&Scene::parse(&json).unwrap()).unwrap();
+        assert_eq!(rebuilt.save_snapshot(), canvas.save_snapshot());
+    }
+
+    #[test]
+    fn errors_point_at_the_faulty_value() {
+        let json = "{\n  \"width\": 10,\n  \"height\": 10,\n  \"obstacles\": [\n    {},\n    { \"shape\": { \"path\": \"M 0 0 X\" } }\n  ]\n}";
+        let error = Scene::parse(json).err().unwrap();
+        assert_eq!(error.path, "obstacles[0]");
+        assert_eq!(error.message, "missing field `shape`");
+        assert_eq!(error.line, 5);
+
+        let json = json.replace("{},", "");
+        let error = Scene::parse(&json).err().unwrap();
+        assert_eq!(error.path, "obstacles[0].shape.path");
+        assert_eq!(error.line, 6);
+    }
+
+    #[test]
+    fn entries_out_of_range_are_rejected() {
+        let scene = |entries: &str| {
+            CanvasDriven::from_scene(&format!(r#"{{ "width": 100, "height": 100, {entries} }}"#))
+                .err()
+        };
+        assert_eq!(
+            scene(r#""emitters": [{ "shape": { "point": [50, 50] }, "params": { "rate": -1 } }]"#),
+            Some(FluidError::InvalidArgument {
+                name: "rate",
+                reason: "must be a non-negative number",
+            })
+        );
+        assert!(scene(
+            r#""emitters": [{ "shape": { "point": [50, 50] }, "params": { "phase": 1 } }]"#
+        )
+        .is_some());
+        assert!(
+            scene(r#""sinks": [{ "circle": { "center": [50, 50], "radius": -5 } }]"#).is_some()
+        );
+        assert!(scene(r#""phases": [{ "density": 0 }]"#).is_some());
+        assert!(scene(r#""force_fields": [{ "drag": { "coefficient": -1 } }]"#).is_some());
+    }
+}
@@ -0,0 +1,77 @@
+/// Steps a frame runs at most by default, about 5 frames at 60 Hz with the default step.
+const MAX_SUBSTEPS: usize = 8;
+
+/// Turns wall-clock time into a whole number of fixed steps, so that the simulation runs
+/// at the same rate whatever the display refresh rate.
+pub(super) struct Scheduler {
+    /// Simulated seconds per real second.
+    pub speed: f64,
+    pub paused: bool,
+    /// Cap on the steps of a single frame, the time beyond it is dropped so that a slow
+    /// frame doesn't make the next ones slower.
+    pub max_substeps: usize,
+    /// Simulated time owed and not yet stepped, less than a step once a frame is done.
+    accumulator: f64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            speed: 1.,
+            paused: false,
+            max_substeps: MAX_SUBSTEPS,
+            accumulator: 0.,
+        }
+    }
+}
+
+impl Scheduler {
+    /// Number of steps of `step` seconds to run for `dt` seconds of real time.
+    pub fn steps(&mut self, dt: f64, step: f64) -> usize {
+        if self.paused {
+            return 0;
+        }
+        self.accumulator += dt * self.speed;
+        let steps = (self.accumulator / step).floor() as usize;
+        if steps > self.max_substeps {
+            self.accumulator = 0.;
+            self.max_substeps
+        } else {
+            self.accumulator -= steps as f64 * step;
+            steps
+        }
+    }
+
+    /// Forgets the time owed, e.g. when pausing or after a restore.
+    pub fn reset(&mut self) {
+        self.accumulator = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_do_not_depend_on_the_frame_rate() {
+        let run = |hz: f64, seconds: f64| {
+            let mut scheduler = Scheduler::default();
+            let frames = (hz * seconds).round() as usize;
+            (0..frames)
+                .map(|_| scheduler.steps(1. / hz, 0.01))
+                .sum::<usize>()
+        };
+        assert!(run(60., 2.).abs_diff(200) <= 1);
+        assert!(run(144., 2.).abs_diff(200) <= 1);
+
+        let mut scheduler = Scheduler {
+            speed: 0.5,
+            ..Scheduler::default()
+        };
+        assert_eq!(scheduler.steps(0.1, 0.01), 5);
+        assert_eq!(scheduler.steps(1., 0.01), MAX_SUBSTEPS);
+        assert_eq!(scheduler.steps(0.005, 0.01), 0);
+        scheduler.paused = true;
+        assert_eq!(scheduler.steps(1., 0.01), 0);
+    }
+}
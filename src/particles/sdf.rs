@@ -0,0 +1,232 @@
+use kurbo::{BezPath, Rect};
+use wasm_bindgen::prelude::*;
+
+use super::{
+    obstacles,
+    particle::{
+        smoothing_kernel_gradient, GeoQuery, Particle, World, PARTICLE_RADIUS, PRESSURE_MULTIPLIER,
+    },
+    v2::V2,
+};
+
+/// Size of a grid cell of a baked distance field.
+pub const SDF_CELL: f64 = PARTICLE_RADIUS;
+/// Cells baked around the domain so particles pushed outside still see the field.
+const SDF_PADDING: usize = 4;
+
+/// How a new shape is combined with the distance field boundary of a world.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdfOp {
+    Union,
+    Intersection,
+    /// Carves the shape out of the boundary, or out of an infinite solid
+    /// when there is no boundary yet, which makes a container.
+    Difference,
+}
+
+/// Analytic signed distance field, negative inside the solid.
+#[derive(Clone, Debug)]
+pub enum SdfShape {
+    Circle {
+        center: V2,
+        radius: f64,
+    },
+    Rect(Rect),
+    /// Closed paths are solid, open paths are thin walls.
+    Path(BezPath),
+    Union(Box<SdfShape>, Box<SdfShape>),
+    Intersection(Box<SdfShape>, Box<SdfShape>),
+    /// The first shape with the second carved out of it.
+    Difference(Box<SdfShape>, Box<SdfShape>),
+    /// Everything outside the shape.
+    Complement(Box<SdfShape>),
+}
+
+impl SdfShape {
+    pub fn distance(&self, point: &V2) -> f64 {
+        match self {
+            SdfShape::Circle { center, radius } => point.distance_to(center) - radius,
+            SdfShape::Rect(rect) => {
+                let center = rect.center();
+                let qx = (point.x - center.x).abs() - rect.width() / 2.;
+                let qy = (point.y - center.y).abs() - rect.height() / 2.;
+                let outside = V2::new(qx.max(0.), qy.max(0.)).len();
+                outside + qx.max(qy).min(0.)
+            }
+            SdfShape::Path(path) => obstacles::path_distance(path, point)
+                .map_or(f64::INFINITY, |(distance, _, _)| distance),
+            SdfShape::Union(a, b) => a.distance(point).min(b.distance(point)),
+            SdfShape::Intersection(a, b) => a.distance(point).max(b.distance(point)),
+            SdfShape::Difference(a, b) => a.distance(point).max(-b.distance(point)),
+            SdfShape::Complement(shape) => -shape.distance(point),
+        }
+    }
+}
+
+/// A distance field sampled on a regular grid, so a lookup costs O(1)
+/// regardless of how complex the shape is.
+#[derive(Clone, Debug)]
+pub struct SdfGrid {
+    origin: V2,
+    columns: usize,
+    rows: usize,
+    values: Vec<f64>,
+}
+
+impl SdfGrid {
+    pub fn bake(shape: &SdfShape, dimensions: &V2) -> SdfGrid {
+        let padding = SDF_PADDING as f64 * SDF_CELL;
+        let origin = V2::new(-padding, -padding);
+        let columns = ((dimensions.x + 2. * padding) / SDF_CELL).ceil() as usize + 1;
+        let rows = ((dimensions.y + 2. * padding) / SDF_CELL).ceil() as usize + 1;
+        let values = (0..rows)
+            .flat_map(|j| (0..columns).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let point = origin + V2::new(i as f64 * SDF_CELL, j as f64 * SDF_CELL);
+                shape.distance(&point)
+            })
+            .collect();
+        SdfGrid {
+            origin,
+            columns,
+            rows,
+            values,
+        }
+    }
+
+    fn value_at(&self, i: usize, j: usize) -> f64 {
+        self.values[j.min(self.rows - 1) * self.columns + i.min(self.columns - 1)]
+    }
+
+    /// Bilinear interpolation of the baked distances.
+    pub fn sample(&self, point: &V2) -> f64 {
+        let local = point.sub(&self.origin) / SDF_CELL;
+        let x = local.x.clamp(0., (self.columns - 1) as f64);
+        let y = local.y.clamp(0., (self.rows - 1) as f64);
+        let (i, j) = (x.floor() as usize, y.floor() as usize);
+        let (fx, fy) = (x - i as f64, y - j as f64);
+        let top = self.value_at(i, j) * (1. - fx) + self.value_at(i + 1, j) * fx;
+        let bottom = self.value_at(i, j + 1) * (1. - fx) + self.value_at(i + 1, j + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    /// Direction of increasing distance, pointing out of the solid.
+    pub fn normal(&self, point: &V2) -> V2 {
+        let e = SDF_CELL / 2.;
+        let dx = self.sample(&(*point + V2::new(e, 0.))) - self.sample(&point.sub(&V2::new(e, 0.)));
+        let dy = self.sample(&(*point + V2::new(0., e))) - self.sample(&point.sub(&V2::new(0., e)));
+        V2::new(dx, dy).normalized()
+    }
+
+    /// Cells whose center lies inside the solid, used to draw the field.
+    pub fn solid_cells(&self) -> impl Iterator<Item = Rect> + '_ {
+        (0..self.rows)
+            .flat_map(move |j| (0..self.columns).map(move |i| (i, j)))
+            .filter(|&(i, j)| self.value_at(i, j) < 0.)
+            .map(|(i, j)| {
+                let x = self.origin.x + i as f64 * SDF_CELL - SDF_CELL / 2.;
+                let y = self.origin.y + j as f64 * SDF_CELL - SDF_CELL / 2.;
+                Rect::new(x, y, x + SDF_CELL, y + SDF_CELL)
+            })
+    }
+}
+
+/// A distance field boundary of a world with its collision response.
+#[derive(Clone, Debug)]
+pub struct SdfBoundary {
+    pub shape: SdfShape,
+    pub grid: SdfGrid,
+    pub restitution: f64,
+    pub friction: f64,
+}
+
+impl SdfBoundary {
+    pub fn new(shape: SdfShape, dimensions: &V2) -> SdfBoundary {
+        SdfBoundary {
+            grid: SdfGrid::bake(&shape, dimensions),
+            shape,
+            restitution: 0.3,
+            friction: 0.1,
+        }
+    }
+}
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// Combines a shape with the distance field boundary and rebakes its grid.
+    pub fn combine_sdf(&mut self, shape: SdfShape, op: SdfOp) {
+        let shape = match (self.sdf.take(), op) {
+            (None, SdfOp::Difference) => SdfShape::Complement(Box::new(shape)),
+            (None, _) => shape,
+            (Some(sdf), SdfOp::Union) => SdfShape::Union(Box::new(sdf.shape), Box::new(shape)),
+            (Some(sdf), SdfOp::Intersection) => {
+                SdfShape::Intersection(Box::new(sdf.shape), Box::new(shape))
+            }
+            (Some(sdf), SdfOp::Difference) => {
+                SdfShape::Difference(Box::new(sdf.shape), Box::new(shape))
+            }
+        };
+        self.sdf = Some(SdfBoundary::new(shape, &self.dimensions));
+    }
+
+    /// Repulsion of the distance field boundary on particles closer than a radius,
+    /// the same penalty particles exert on each other.
+    pub(super) fn calc_sdf_acc(&self, particle: &Particle) -> V2 {
+        let Some(sdf) = self.sdf.as_ref() else {
+            return V2::new(0., 0.);
+        };
+        let distance = sdf.grid.sample(&particle.position);
+        if distance >= PARTICLE_RADIUS {
+            return V2::new(0., 0.);
+        }
+        let kernel = smoothing_kernel_gradient(distance.max(0.));
+        (PRESSURE_MULTIPLIER * kernel / particle.mass) * sdf.grid.normal(&particle.position)
+    }
+
+    /// Projects particles inside the solid back to its surface.
+    pub(super) fn resolve_sdf(&self, particle: &mut Particle) {
+        let Some(sdf) = self.sdf.as_ref() else {
+            return;
+        };
+        let distance = sdf.grid.sample(&particle.position);
+        if distance >= 0. {
+            return;
+        }
+        let normal = sdf.grid.normal(&particle.position);
+        let surface = particle.position + (-distance) * normal;
+        obstacles::respond(particle, surface, normal, sdf.restitution, sdf.friction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difference_carves_a_container() {
+        let container = SdfShape::Difference(
+            Box::new(SdfShape::Rect(Rect::new(-10., -10., 110., 110.))),
+            Box::new(SdfShape::Circle {
+                center: V2::new(50., 50.),
+                radius: 40.,
+            }),
+        );
+        let grid = SdfGrid::bake(&container, &V2::new(100., 100.));
+        assert!(grid.sample(&V2::new(50., 50.)) > 0.);
+        assert!(grid.sample(&V2::new(2., 2.)) < 0.);
+        assert!((grid.sample(&V2::new(50., 20.)) - 10.).abs() < 0.5);
+        let normal = grid.normal(&V2::new(50., 95.));
+        assert!(normal.y < -0.9);
+    }
+
+    #[test]
+    fn closed_subpaths_are_inside_and_open_ones_are_thin() {
+        let path = BezPath::from_svg("M 0 0 L 20 0 L 20 20 L 0 20 Z M 40 0 L 60 0").unwrap();
+        let shape = SdfShape::Path(path);
+        assert!((shape.distance(&V2::new(10., 5.)) + 5.).abs() < 1e-6);
+        assert!(
+            (shape.distance(&V2::new(50., 10.)) - (10. - obstacles::PATH_THICKNESS)).abs() < 1e-6
+        );
+        assert!(shape.distance(&V2::new(50., 1.)) < 0.);
+    }
+}
@@ -0,0 +1,746 @@
+use kurbo::{BezPath, Circle, PathEl, Point, Rect};
+
+use crate::snapshot::{impl_struct, impl_unit_enum, Decode, Encode, Reader, SnapshotError, Writer};
+
+use super::{
+    attributes::{Channels, Rgba},
+    emitters::{Emitter, EmitterParams, EmitterShape, Sink},
+    forces::{DragField, FieldSpec, NoiseField, RadialField, UniformField, VortexField},
+    gravity::Gravity,
+    obstacles::{Obstacle, ObstacleShape},
+    params::{SimulationParams, Solver, SolverStats, WallMode, WallParams},
+    particle::{GeoQuery, Particle, ParticleKind, World},
+    phases::Phase,
+    rng::Rng,
+    sdf::{SdfBoundary, SdfShape},
+    sph::SphState,
+    tools::{Grab, Paint, Pointer, PointerState, Tool},
+    v2::V2,
+    TreeType,
+};
+
+impl_struct!(V2 { x, y });
+impl_struct!(Rgba { r, g, b, a });
+impl_struct!(Phase {
+    density,
+    viscosity,
+    color
+});
+impl_struct!(SphState {
+    number_density,
+    normal,
+    alpha,
+    kappa
+});
+impl_struct!(SolverStats {
+    density_iterations,
+    density_error,
+    divergence_iterations,
+    divergence_error
+});
+impl_struct!(WallParams {
+    mode,
+    restitution,
+    friction
+});
+impl_struct!(Grab { pointer, offset });
+impl_struct!(Pointer {
+    position,
+    pressure,
+    tool,
+    field
+});
+impl_struct!(PointerState {
+    mouse_pos,
+    is_pressing_mouse,
+    pointers,
+    grabs
+});
+impl_struct!(SimulationParams {
+    solver,
+    dt,
+    surface_tension,
+    pbf_iterations,
+    xsph_viscosity,
+    density_tolerance,
+    divergence_tolerance,
+    max_pressure_iterations,
+    boundary_particles,
+    left_wall,
+    right_wall,
+    top_wall,
+    bottom_wall,
+});
+impl_struct!(EmitterParams {
+    velocity_x,
+    velocity_y,
+    rate,
+    spread,
+    phase
+});
+impl_struct!(Emitter {
+    shape,
+    params,
+    pending
+});
+impl_struct!(Obstacle {
+    shape,
+    restitution,
+    friction
+});
+impl_struct!(UniformField { acceleration });
+impl_struct!(RadialField {
+    center,
+    radius,
+    strength,
+    falloff
+});
+impl_struct!(VortexField {
+    center,
+    radius,
+    strength,
+    falloff
+});
+impl_struct!(DragField { coefficient });
+impl_struct!(NoiseField {
+    strength,
+    scale,
+    speed
+});
+
+impl_unit_enum!(Solver {
+    Force = 0,
+    PositionBased = 1,
+    Incompressible = 2,
+});
+impl_unit_enum!(WallMode {
+    Reflective = 0,
+    Periodic = 1,
+    Open = 2,
+    Absorbing = 3,
+});
+impl_unit_enum!(Tool {
+    Attract = 0,
+    Repel = 1,
+    Drag = 2,
+    Spawn = 3,
+    Erase = 4,
+    Paint = 5,
+});
+impl_unit_enum!(TreeType {
+    ZOrder = 0,
+    Hilbert = 1,
+    Quad = 2,
+    RStar = 3,
+    HashGrid = 4,
+});
+
+impl Encode for Point {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.x);
+        writer.put(&self.y);
+    }
+}
+
+impl Decode for Point {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Point::new(reader.get()?, reader.get()?))
+    }
+}
+
+impl Encode for Rect {
+    fn encode(&self, writer: &mut Writer) {
+        [self.x0, self.y0, self.x1, self.y1]
+            .iter()
+            .for_each(|value| writer.put(value));
+    }
+}
+
+impl Decode for Rect {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Rect::new(
+            reader.get()?,
+            reader.get()?,
+            reader.get()?,
+            reader.get()?,
+        ))
+    }
+}
+
+impl Encode for Circle {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.center);
+        writer.put(&self.radius);
+    }
+}
+
+impl Decode for Circle {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        let center: Point = reader.get()?;
+        Ok(Circle::new(center, reader.get::<f64>()?))
+    }
+}
+
+impl Encode for PathEl {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            PathEl::MoveTo(p) => {
+                writer.put(&0u8);
+                writer.put(p);
+            }
+            PathEl::LineTo(p) => {
+                writer.put(&1u8);
+                writer.put(p);
+            }
+            PathEl::QuadTo(c, p) => {
+                writer.put(&2u8);
+                writer.put(c);
+                writer.put(p);
+            }
+            PathEl::CurveTo(c1, c2, p) => {
+                writer.put(&3u8);
+                writer.put(c1);
+                writer.put(c2);
+                writer.put(p);
+            }
+            PathEl::ClosePath => writer.put(&4u8),
+        }
+    }
+}
+
+impl Decode for PathEl {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(PathEl::MoveTo(reader.get()?)),
+            1 => Ok(PathEl::LineTo(reader.get()?)),
+            2 => Ok(PathEl::QuadTo(reader.get()?, reader.get()?)),
+            3 => Ok(PathEl::CurveTo(reader.get()?, reader.get()?, reader.get()?)),
+            4 => Ok(PathEl::ClosePath),
+            _ => Err(SnapshotError::Invalid("path element")),
+        }
+    }
+}
+
+impl Encode for BezPath {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(self.elements());
+    }
+}
+
+impl Decode for BezPath {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(BezPath::from_vec(reader.get()?))
+    }
+}
+
+impl Encode for ParticleKind {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            ParticleKind::Fluid => writer.put(&0u8),
+            ParticleKind::Boundary { volume } => {
+                writer.put(&1u8);
+                writer.put(volume);
+            }
+        }
+    }
+}
+
+impl Decode for ParticleKind {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(ParticleKind::Fluid),
+            1 => Ok(ParticleKind::Boundary {
+                volume: reader.get()?,
+            }),
+            _ => Err(SnapshotError::Invalid("particle kind")),
+        }
+    }
+}
+
+/// Particles are saved without their grab, pointers are input and not part of snapshots.
+impl Encode for Particle {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.position);
+        writer.put(&self.velocity);
+        writer.put(&self.kind);
+        writer.put(&self.mass);
+        writer.put(&self.phase);
+        writer.put(&self.color);
+        writer.put(&self.channels);
+        writer.put(&self.sph);
+    }
+}
+
+impl Decode for Particle {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Particle {
+            position: reader.get()?,
+            velocity: reader.get()?,
+            kind: reader.get()?,
+            mass: reader.get()?,
+            phase: reader.get()?,
+            color: reader.get()?,
+            channels: reader.get()?,
+            sph: reader.get()?,
+            grab: None,
+            slot: 0,
+        })
+    }
+}
+
+impl Encode for ObstacleShape {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            ObstacleShape::Circle(circle) => {
+                writer.put(&0u8);
+                writer.put(circle);
+            }
+            ObstacleShape::Rect(rect) => {
+                writer.put(&1u8);
+                writer.put(rect);
+            }
+            ObstacleShape::Path(path) => {
+                writer.put(&2u8);
+                writer.put(path);
+            }
+        }
+    }
+}
+
+impl Decode for ObstacleShape {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(ObstacleShape::Circle(reader.get()?)),
+            1 => Ok(ObstacleShape::Rect(reader.get()?)),
+            2 => Ok(ObstacleShape::Path(reader.get()?)),
+            _ => Err(SnapshotError::Invalid("obstacle shape")),
+        }
+    }
+}
+
+impl Encode for SdfShape {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            SdfShape::Circle { center, radius } => {
+                writer.put(&0u8);
+                writer.put(center);
+                writer.put(radius);
+            }
+            SdfShape::Rect(rect) => {
+                writer.put(&1u8);
+                writer.put(rect);
+            }
+            SdfShape::Path(path) => {
+                writer.put(&2u8);
+                writer.put(path);
+            }
+            SdfShape::Union(a, b) => {
+                writer.put(&3u8);
+                writer.put(a.as_ref());
+                writer.put(b.as_ref());
+            }
+            SdfShape::Intersection(a, b) => {
+                writer.put(&4u8);
+                writer.put(a.as_ref());
+                writer.put(b.as_ref());
+            }
+            SdfShape::Difference(a, b) => {
+                writer.put(&5u8);
+                writer.put(a.as_ref());
+                writer.put(b.as_ref());
+            }
+            SdfShape::Complement(shape) => {
+                writer.put(&6u8);
+                writer.put(shape.as_ref());
+            }
+        }
+    }
+}
+
+impl Decode for SdfShape {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(SdfShape::Circle {
+                center: reader.get()?,
+                radius: reader.get()?,
+            }),
+            1 => Ok(SdfShape::Rect(reader.get()?)),
+            2 => Ok(SdfShape::Path(reader.get()?)),
+            3 => Ok(SdfShape::Union(
+                Box::new(reader.get()?),
+                Box::new(reader.get()?),
+            )),
+            4 => Ok(SdfShape::Intersection(
+                Box::new(reader.get()?),
+                Box::new(reader.get()?),
+            )),
+            5 => Ok(SdfShape::Difference(
+                Box::new(reader.get()?),
+                Box::new(reader.get()?),
+            )),
+            6 => Ok(SdfShape::Complement(Box::new(reader.get()?))),
+            _ => Err(SnapshotError::Invalid("distance field shape")),
+        }
+    }
+}
+
+impl Encode for FieldSpec {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            FieldSpec::Uniform(field) => {
+                writer.put(&0u8);
+                writer.put(field);
+            }
+            FieldSpec::Radial(field) => {
+                writer.put(&1u8);
+                writer.put(field);
+            }
+            FieldSpec::Vortex(field) => {
+                writer.put(&2u8);
+                writer.put(field);
+            }
+            FieldSpec::Drag(field) => {
+                writer.put(&3u8);
+                writer.put(field);
+            }
+            FieldSpec::Noise(field) => {
+                writer.put(&4u8);
+                writer.put(field);
+            }
+        }
+    }
+}
+
+impl Decode for FieldSpec {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(FieldSpec::Uniform(reader.get()?)),
+            1 => Ok(FieldSpec::Radial(reader.get()?)),
+            2 => Ok(FieldSpec::Vortex(reader.get()?)),
+            3 => Ok(FieldSpec::Drag(reader.get()?)),
+            4 => Ok(FieldSpec::Noise(reader.get()?)),
+            _ => Err(SnapshotError::Invalid("force field")),
+        }
+    }
+}
+
+impl Encode for EmitterShape {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            EmitterShape::Point(position) => {
+                writer.put(&0u8);
+                writer.put(position);
+            }
+            EmitterShape::Line(from, to) => {
+                writer.put(&1u8);
+                writer.put(from);
+                writer.put(to);
+            }
+            EmitterShape::Nozzle { position, width } => {
+                writer.put(&2u8);
+                writer.put(position);
+                writer.put(width);
+            }
+        }
+    }
+}
+
+impl Decode for EmitterShape {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(EmitterShape::Point(reader.get()?)),
+            1 => Ok(EmitterShape::Line(reader.get()?, reader.get()?)),
+            2 => Ok(EmitterShape::Nozzle {
+                position: reader.get()?,
+                width: reader.get()?,
+            }),
+            _ => Err(SnapshotError::Invalid("emitter shape")),
+        }
+    }
+}
+
+impl Encode for Sink {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            Sink::Circle(circle) => {
+                writer.put(&0u8);
+                writer.put(circle);
+            }
+            Sink::Rect(rect) => {
+                writer.put(&1u8);
+                writer.put(rect);
+            }
+        }
+    }
+}
+
+impl Decode for Sink {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(Sink::Circle(reader.get()?)),
+            1 => Ok(Sink::Rect(reader.get()?)),
+            _ => Err(SnapshotError::Invalid("sink")),
+        }
+    }
+}
+
+impl Encode for Gravity {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            Gravity::Uniform(acceleration) => {
+                writer.put(&0u8);
+                writer.put(acceleration);
+            }
+            Gravity::Point { center, strength } => {
+                writer.put(&1u8);
+                writer.put(center);
+                writer.put(strength);
+            }
+            Gravity::Zero => writer.put(&2u8),
+        }
+    }
+}
+
+impl Decode for Gravity {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(Gravity::Uniform(reader.get()?)),
+            1 => Ok(Gravity::Point {
+                center: reader.get()?,
+                strength: reader.get()?,
+            }),
+            2 => Ok(Gravity::Zero),
+            _ => Err(SnapshotError::Invalid("gravity")),
+        }
+    }
+}
+
+impl Encode for Paint {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            Paint::Color(color) => {
+                writer.put(&0u8);
+                writer.put(color);
+            }
+            Paint::Phase(phase) => {
+                writer.put(&1u8);
+                writer.put(phase);
+            }
+        }
+    }
+}
+
+impl Decode for Paint {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(Paint::Color(reader.get()?)),
+            1 => Ok(Paint::Phase(reader.get()?)),
+            _ => Err(SnapshotError::Invalid("paint")),
+        }
+    }
+}
+
+impl Encode for Rng {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.state());
+    }
+}
+
+impl Decode for Rng {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Rng::new(reader.get()?))
+    }
+}
+
+impl Encode for Channels {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(self.names());
+    }
+}
+
+impl Decode for Channels {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        let names: Vec<String> = reader.get()?;
+        let mut channels = Channels::default();
+        names.iter().for_each(|name| {
+            channels.register(name);
+        });
+        Ok(channels)
+    }
+}
+
+impl Encode for SdfBoundary {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.shape);
+        writer.put(&self.restitution);
+        writer.put(&self.friction);
+    }
+}
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// Writes everything needed to continue the simulation exactly where it is.
+    /// Pointers, the particles they grab and force fields that can't be saved,
+    /// like closures, are left out.
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.dimensions);
+        writer.put(&self.gravity);
+        writer.put(&self.time);
+        writer.put(&self.params);
+        writer.put(&self.stats);
+        writer.put(&self.rng);
+        writer.put(&self.channels);
+        writer.put(&self.phases);
+        writer.put(&self.particles);
+        writer.put(&self.obstacles);
+        writer.put(&self.emitters);
+        writer.put(&self.sinks);
+        writer.put(&self.sdf);
+        self.saved_force_fields().iter().for_each(|(id, spec)| {
+            writer.put(&true);
+            writer.put(id);
+            writer.put(spec);
+        });
+        writer.put(&false);
+        writer.put(&self.next_field_id);
+        writer.put(&self.tool);
+        writer.put(&self.tool_radius);
+        writer.put(&self.paint);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<World<T>, SnapshotError> {
+        let dimensions: V2 = reader.get()?;
+        let mut world = World::new(dimensions, reader.get()?, 0);
+        world.time = reader.get()?;
+        world.params = reader.get()?;
+        world.stats = reader.get()?;
+        world.rng = reader.get()?;
+        world.channels = reader.get()?;
+        world.phases = reader.get()?;
+        if world.phases.is_empty() {
+            return Err(SnapshotError::Invalid("phases"));
+        }
+        world
+            .params
+            .validate()
+            .map_err(|_| SnapshotError::Invalid("params"))?;
+        world.particles = reader.get()?;
+        world.particles.iter().try_for_each(|particle| {
+            if particle.channels.len() != world.channels.len() {
+                Err(SnapshotError::Invalid("particle channels"))
+            } else if !particle.is_finite() || !particle.mass.is_finite() || particle.mass <= 0. {
+                Err(SnapshotError::Invalid("particle"))
+            } else {
+                Ok(())
+            }
+        })?;
+        world.obstacles = reader.get()?;
+        world.emitters = reader.get()?;
+        world.sinks = reader.get()?;
+        // the grid is baked again rather than saved
+        if reader.get()? {
+            let mut sdf = SdfBoundary::new(reader.get()?, &dimensions);
+            sdf.restitution = reader.get()?;
+            sdf.friction = reader.get()?;
+            world.sdf = Some(sdf);
+        }
+        while reader.get()? {
+            let id: u32 = reader.get()?;
+            let spec: FieldSpec = reader.get()?;
+            world.force_fields.push((id, spec.into_field()));
+        }
+        world.next_field_id = reader.get()?;
+        world.tool = reader.get()?;
+        world.tool_radius = reader.get()?;
+        world.paint = reader.get()?;
+        world.update_tree();
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{hash_grid::HashGrid, CanvasDriven, CanvasDrivenArgs, SdfOp};
+    use super::*;
+    use crate::snapshot::SnapshotKind;
+
+    #[test]
+    fn snapshot_continues_the_same_trajectory() {
+        let mut params = SimulationParams::default();
+        params.solver = Solver::PositionBased;
+        params.boundary_particles = true;
+        let mut canvas = CanvasDriven::new(CanvasDrivenArgs {
+            tree_type: TreeType::HashGrid,
+            particles: 150,
+            params,
+            seed: Some(7),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        let mut emitter = EmitterParams::default();
+        emitter.spread = 0.5;
+        canvas.add_point_emitter(400., 100., emitter).unwrap();
+        canvas.add_noise_field(50., 80., 0.5).unwrap();
+        canvas.sdf_circle(400., 400., 60., SdfOp::Union).unwrap();
+        canvas.evolve(20).unwrap();
+        let snapshot = canvas.save_snapshot();
+        canvas.evolve(20).unwrap();
+
+        let mut restored = CanvasDriven::new(CanvasDrivenArgs {
+            seed: Some(1),
+            ..CanvasDrivenArgs::default()
+        })
+        .unwrap();
+        restored.restore(&snapshot).unwrap();
+        restored.evolve(20).unwrap();
+        let positions = |canvas: &CanvasDriven| -> Vec<V2> {
+            canvas
+                .world
+                .particles()
+                .iter()
+                .map(|p| p.position)
+                .collect()
+        };
+        assert_eq!(positions(&restored), positions(&canvas));
+        assert_eq!(restored.save_snapshot(), canvas.save_snapshot());
+    }
+
+    #[test]
+    fn a_world_without_phases_is_rejected() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(100., 100.), Gravity::Zero, 0);
+        world.phases.clear();
+        let mut writer = Writer::new(SnapshotKind::Fluid);
+        world.encode(&mut writer);
+        let bytes = writer.finish();
+        let mut reader = Reader::new(&bytes, SnapshotKind::Fluid).unwrap();
+        assert!(matches!(
+            World::<HashGrid<Particle>>::decode(&mut reader),
+            Err(SnapshotError::Invalid("phases"))
+        ));
+    }
+
+    #[test]
+    fn particles_out_of_step_with_the_world_are_rejected() {
+        let decode = |world: &World<HashGrid<Particle>>| {
+            let mut writer = Writer::new(SnapshotKind::Fluid);
+            world.encode(&mut writer);
+            let bytes = writer.finish();
+            let mut reader = Reader::new(&bytes, SnapshotKind::Fluid).unwrap();
+            World::<HashGrid<Particle>>::decode(&mut reader).map(|_| ())
+        };
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(100., 100.), Gravity::Zero, 0);
+        world.add_channel("dye");
+        world.add_particle(V2::new(10., 10.), V2::new(0., 0.));
+        assert_eq!(decode(&world), Ok(()));
+        world.particles[0].channels.clear();
+        assert_eq!(
+            decode(&world),
+            Err(SnapshotError::Invalid("particle channels"))
+        );
+        world.particles[0].channels.push(0.);
+        world.particles[0].mass = 0.;
+        assert_eq!(decode(&world), Err(SnapshotError::Invalid("particle")));
+        world.particles[0].mass = 1.;
+        world.params.dt = f64::NAN;
+        assert_eq!(decode(&world), Err(SnapshotError::Invalid("params")));
+    }
+}
@@ -0,0 +1,67 @@
+use super::{particle::PARTICLE_RADIUS, v2::V2};
+
+pub const SMOOTHING_RADIUS: f64 = PARTICLE_RADIUS * 2.;
+
+const H2: f64 = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+const H4: f64 = H2 * H2;
+const POLY6_NORMALIZATION: f64 = 4. / (std::f64::consts::PI * H4 * H4);
+const SPIKY_GRADIENT_NORMALIZATION: f64 = -30. / (std::f64::consts::PI * H4 * SMOOTHING_RADIUS);
+
+/// Per particle values recomputed by the SPH passes on every step.
+///
+/// Densities follow the multiphase number density formulation (Solenthaler & Pajarola 2008):
+/// `number_density` is `sum W_ij` and the physical density is `mass * number_density`,
+/// which keeps interfaces between fluids of different rest densities stable.
+#[derive(Clone, Debug, Default)]
+pub struct SphState {
+    pub number_density: f64,
+    pub normal: V2,
+    /// DFSPH stiffness factor.
+    pub alpha: f64,
+    pub kappa: f64,
+}
+
+pub fn poly6(d: f64) -> f64 {
+    if d >= SMOOTHING_RADIUS {
+        return 0.;
+    }
+    let v = SMOOTHING_RADIUS * SMOOTHING_RADIUS - d * d;
+    POLY6_NORMALIZATION * v * v * v
+}
+
+/// Gradient of the spiky kernel with respect to the first particle, `r` being `x_i - x_j`.
+pub fn spiky_gradient(r: &V2) -> V2 {
+    let d = r.len();
+    if !(0.001..SMOOTHING_RADIUS).contains(&d) {
+        return V2::new(0., 0.);
+    }
+    let v = SMOOTHING_RADIUS - d;
+    r.scalar_mul(SPIKY_GRADIENT_NORMALIZATION * v * v / d)
+}
+
+/// Akinci et al. 2013 cohesion spline, scaled so that it peaks at 1 for `d = h / 2`
+/// and is -1 at the origin.
+pub fn cohesion(d: f64) -> f64 {
+    let h = SMOOTHING_RADIUS;
+    if d >= h {
+        return 0.;
+    }
+    let h6 = h.powi(6);
+    let v = (h - d).powi(3) * d.powi(3);
+    let c = if 2. * d > h { v } else { 2. * v - h6 / 64. };
+    c * 64. / h6
+}
+
+/// Number density of a particle sitting inside a square lattice with the given spacing,
+/// used as the rest number density of every phase.
+pub fn lattice_number_density(spacing: f64) -> f64 {
+    let n = (SMOOTHING_RADIUS / spacing).ceil() as i32;
+    let mut density = 0.;
+    for i in -n..=n {
+        for j in -n..=n {
+            let d = V2::new(i as f64 * spacing, j as f64 * spacing).len();
+            density += poly6(d);
+        }
+    }
+    density
+}
@@ -0,0 +1,418 @@
+use wasm_bindgen::prelude::*;
+
+use super::{
+    attributes::Rgba,
+    forces::RadialField,
+    particle::{GeoQuery, Particle, World, PARTICLE_RADIUS},
+    v2::V2,
+};
+
+/// What pressing the pointer does to the particles within the tool radius.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tool {
+    Attract,
+    Repel,
+    /// Grabs the particles under the pointer and drags them along with a spring.
+    Drag,
+    /// Fills the empty space of the brush with particles.
+    Spawn,
+    Erase,
+    /// Applies the world's `Paint` to the particles under the brush.
+    Paint,
+}
+
+/// What the paint tool, and the particles spawned by the spawn tool, get.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Paint {
+    Color(Rgba),
+    Phase(u32),
+}
+
+/// A particle held by the drag tool, `offset` is its position relative to the pointer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grab {
+    pub pointer: u32,
+    pub offset: V2,
+}
+
+/// A pressed mouse button, pen or finger using a tool.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pointer {
+    pub position: V2,
+    /// Scales the attract and repel forces, 1 for a mouse.
+    pub pressure: f64,
+    pub tool: Tool,
+    /// Id of the force field of an attract or repel pointer.
+    pub(super) field: Option<u32>,
+}
+
+/// The pressed pointers and what they hold, which snapshots leave out as they are input.
+/// Recordings start with it, so that a replay starts with the same pointers pressed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct PointerState {
+    pub mouse_pos: Option<V2>,
+    pub is_pressing_mouse: bool,
+    pub pointers: Vec<(u32, Pointer)>,
+    /// Particles held by the drag tool, by index.
+    pub grabs: Vec<(usize, Grab)>,
+}
+
+/// Pointer id of the mouse driven by `World::update_mouse_pos`.
+pub const MOUSE_POINTER: u32 = u32::MAX;
+const TOOL_FORCE: f64 = 200.;
+const SPRING_STIFFNESS: f64 = 400.;
+/// Critical damping of the drag spring, `2 * sqrt(stiffness)`.
+const SPRING_DAMPING: f64 = 40.;
+
+impl<T: GeoQuery<Particle>> World<T> {
+    /// Sets the tool of the mouse and the radius of every tool, the fields of the pressed
+    /// pointers take the new radius while particles already dragged stay grabbed.
+    pub fn set_tool(&mut self, tool: Tool, radius: f64) {
+        self.tool = tool;
+        self.tool_radius = radius;
+        let ids: Vec<u32> = self.pointers.keys().copied().collect();
+        ids.into_iter().for_each(|id| self.update_pointer_field(id));
+        // presses the mouse again so that the new tool takes over
+        if let Some(pointer) = self.pointers.get(&MOUSE_POINTER).cloned() {
+            self.remove_pointer(MOUSE_POINTER);
+            self.add_pointer(MOUSE_POINTER, pointer.position, pointer.pressure, tool);
+        }
+    }
+
+    pub fn update_mouse_pos(&mut self, mouse_pos: Option<V2>, is_pressing: bool) {
+        self.mouse_pos = mouse_pos;
+        self.is_pressing_mouse = is_pressing && mouse_pos.is_some();
+        match mouse_pos.filter(|_| self.is_pressing_mouse) {
+            Some(position) if self.pointers.contains_key(&MOUSE_POINTER) => {
+                self.move_pointer(MOUSE_POINTER, position, 1.);
+            }
+            Some(position) => self.add_pointer(MOUSE_POINTER, position, 1., self.tool),
+            None => self.remove_pointer(MOUSE_POINTER),
+        }
+    }
+
+    /// Presses a pointer, replacing any pointer with the same id.
+    pub fn add_pointer(&mut self, id: u32, position: V2, pressure: f64, tool: Tool) {
+        self.remove_pointer(id);
+        if tool == Tool::Drag {
+            self.grab(id, position);
+        }
+        let mut pointer = Pointer {
+            position,
+            pressure,
+            tool,
+            field: None,
+        };
+        pointer.field = self.add_pointer_field(&pointer);
+        self.pointers.insert(id, pointer);
+    }
+
+    /// Moves a pointer, its attract or repel field follows it under the same id.
+    pub fn move_pointer(&mut self, id: u32, position: V2, pressure: f64) {
+        let Some(pointer) = self.pointers.get_mut(&id) else {
+            return;
+        };
+        pointer.position = position;
+        pointer.pressure = pressure;
+        self.update_pointer_field(id);
+    }
+
+    /// Replaces the field of a pointer, under the same id, after it moved or changed.
+    fn update_pointer_field(&mut self, id: u32) {
+        let Some(pointer) = self.pointers.get(&id) else {
+            return;
+        };
+        let (Some(id), Some(field)) = (pointer.field, self.pointer_field(pointer)) else {
+            return;
+        };
+        if let Some((_, slot)) = self
+            .force_fields
+            .iter_mut()
+            .find(|(field_id, _)| *field_id == id)
+        {
+            *slot = Box::new(field);
+        }
+    }
+
+    /// Lifts a pointer, letting go of the particles it drags.
+    pub fn remove_pointer(&mut self, id: u32) {
+        let Some(pointer) = self.pointers.remove(&id) else {
+            return;
+        };
+        if let Some(field) = pointer.field {
+            self.force_fields.retain(|(id, _)| *id != field);
+        }
+        self.particles
+            .iter_mut()
+            .filter(|particle| particle.grab.is_some_and(|grab| grab.pointer == id))
+            .for_each(|particle| particle.grab = None);
+    }
+
+    pub(super) fn pointer_state(&self) -> PointerState {
+        PointerState {
+            mouse_pos: self.mouse_pos,
+            is_pressing_mouse: self.is_pressing_mouse,
+            pointers: self
+                .pointers
+                .iter()
+                .map(|(id, pointer)| (*id, pointer.clone()))
+                .collect(),
+            grabs: self
+                .particles
+                .iter()
+                .enumerate()
+                .filter_map(|(index, particle)| Some((index, particle.grab?)))
+                .collect(),
+        }
+    }
+
+    /// Lifts the current pointers and presses the ones of `state`, whose fields get
+    /// back their ids and their places among the force fields.
+    pub(super) fn restore_pointer_state(&mut self, state: PointerState) {
+        let ids: Vec<u32> = self.pointers.keys().copied().collect();
+        ids.into_iter().for_each(|id| self.remove_pointer(id));
+        self.mouse_pos = state.mouse_pos;
+        self.is_pressing_mouse = state.is_pressing_mouse;
+        state.pointers.into_iter().for_each(|(id, pointer)| {
+            if let (Some(field_id), Some(field)) = (pointer.field, self.pointer_field(&pointer)) {
+                // ids only grow, so the fields are sorted by id
+                let index = self
+                    .force_fields
+                    .partition_point(|(other, _)| *other < field_id);
+                self.force_fields.insert(index, (field_id, Box::new(field)));
+            }
+            self.pointers.insert(id, pointer);
+        });
+        state.grabs.into_iter().for_each(|(index, grab)| {
+            if let Some(particle) = self.particles.get_mut(index) {
+                particle.grab = Some(grab);
+            }
+        });
+    }
+
+    fn add_pointer_field(&mut self, pointer: &Pointer) -> Option<u32> {
+        let field = self.pointer_field(pointer)?;
+        Some(self.add_force_field(Box::new(field)))
+    }
+
+    fn pointer_field(&self, pointer: &Pointer) -> Option<RadialField> {
+        let strength = match pointer.tool {
+            Tool::Attract => TOOL_FORCE,
+            Tool::Repel => -TOOL_FORCE,
+            _ => return None,
+        };
+        Some(RadialField {
+            center: pointer.position,
+            radius: self.tool_radius,
+            strength: strength * pointer.pressure,
+            falloff: 0.,
+        })
+    }
+
+    /// Ids of the force fields owned by pointers, which only the pointers remove.
+    pub(super) fn pointer_fields(&self) -> Vec<u32> {
+        self.pointers
+            .values()
+            .filter_map(|pointer| pointer.field)
+            .collect()
+    }
+
+    fn grab(&mut self, pointer: u32, center: V2) {
+        let radius = self.tool_radius;
+        self.particles.iter_mut().for_each(|particle| {
+            let offset = particle.position.sub(&center);
+            if offset.len() <= radius {
+                particle.grab = Some(Grab { pointer, offset });
+            }
+        });
+    }
+
+    /// Spring pulling a grabbed particle to its place relative to the pointer.
+    pub(super) fn calc_grab_acc(&self, particle: &Particle) -> V2 {
+        let Some(grab) = particle.grab else {
+            return V2::new(0., 0.);
+        };
+        let Some(pointer) = self.pointers.get(&grab.pointer) else {
+            return V2::new(0., 0.);
+        };
+        let target = pointer.position + grab.offset;
+        SPRING_STIFFNESS * target.sub(&particle.position) + (-SPRING_DAMPING) * particle.velocity
+    }
+
+    /// Applies the brush tools of the pressed pointers, called once per step.
+    pub(super) fn apply_tools(&mut self) {
+        let brushes: Vec<(V2, Tool)> = self
+            .pointers
+            .values()
+            .map(|pointer| (pointer.position, pointer.tool))
+            .collect();
+        if brushes.iter().any(|(_, tool)| *tool == Tool::Spawn) {
+            self.update_tree();
+        }
+        let mut spawned = Vec::new();
+        brushes
+            .into_iter()
+            .for_each(|(center, tool)| self.apply_tool(tool, center, &mut spawned));
+    }
+
+    fn apply_tool(&mut self, tool: Tool, center: V2, spawned: &mut Vec<V2>) {
+        let radius = self.tool_radius;
+        match tool {
+            Tool::Spawn => self.spawn_in_brush(center, radius, spawned),
+            Tool::Erase => {
+                self.remove_particles_in_radius(&center, radius);
+            }
+            Tool::Paint => {
+                let paint = self.paint;
+                let inside: Vec<usize> = (0..self.particles.len())
+                    .filter(|&i| self.particles[i].position.distance_to(&center) <= radius)
+                    .collect();
+                inside
+                    .into_iter()
+                    .for_each(|i| self.paint_particle(i, paint));
+            }
+            Tool::Attract | Tool::Repel | Tool::Drag => {}
+        }
+    }
+
+    fn paint_particle(&mut self, index: usize, paint: Paint) {
+        match paint {
+            Paint::Color(color) => self.particles[index].color = color,
+            Paint::Phase(phase) => self.set_particle_phase(index, phase),
+        }
+    }
+
+    /// Puts particles on the lattice points of the brush that are inside the fluid
+    /// domain and not already taken by a particle. The tree is built once for all the
+    /// brushes of a step, `spawned` holds what the previous brushes added since.
+    fn spawn_in_brush(&mut self, center: V2, radius: f64, spawned: &mut Vec<V2>) {
+        let steps = (radius / PARTICLE_RADIUS).floor() as i64;
+        let mut positions = Vec::new();
+        for i in -steps..=steps {
+            for j in -steps..=steps {
+                let offset = V2::new(i as f64, j as f64) * PARTICLE_RADIUS;
+                let position = center + offset;
+                if offset.len() > radius || !self.is_free(&position) {
+                    continue;
+                }
+                let mut taken = spawned
+                    .iter()
+                    .any(|other| other.distance_to(&position) < PARTICLE_RADIUS * 0.9);
+                self.tree
+                    .query_distance(&position, PARTICLE_RADIUS * 0.9, |_| taken = true);
+                if !taken {
+                    positions.push(position);
+                }
+            }
+        }
+        let paint = self.paint;
+        positions.into_iter().for_each(|position| {
+            self.push_particle(Particle::new(position, V2::new(0., 0.)));
+            self.paint_particle(self.particles.len() - 1, paint);
+            spawned.push(position);
+        });
+    }
+
+    /// Whether a point lies inside the domain and outside every solid.
+    pub(super) fn is_free(&self, position: &V2) -> bool {
+        let inside_domain = (0.0..=self.dimensions.x).contains(&position.x)
+            && (0.0..=self.dimensions.y).contains(&position.y);
+        let inside_solid = self
+            .obstacles
+            .iter()
+            .any(|obstacle| obstacle.contact(position).is_some())
+            || self
+                .sdf
+                .as_ref()
+                .is_some_and(|sdf| sdf.grid.sample(position) < 0.);
+        inside_domain && !inside_solid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::{gravity::Gravity, hash_grid::HashGrid};
+
+    #[test]
+    fn pointers_drag_their_own_particles() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(200., 200.), Gravity::Zero, 0);
+        world.push_particle(Particle::new(V2::new(50., 50.), V2::new(0., 0.)));
+        world.push_particle(Particle::new(V2::new(150., 150.), V2::new(0., 0.)));
+        world.set_tool(Tool::Drag, 10.);
+        world.add_pointer(1, V2::new(50., 50.), 1., Tool::Drag);
+        world.add_pointer(2, V2::new(150., 150.), 1., Tool::Drag);
+        world.move_pointer(1, V2::new(60., 50.), 1.);
+        world.move_pointer(2, V2::new(150., 140.), 1.);
+        world.checked_evolve(200).unwrap();
+        assert!(world.particles[0].position.distance_to(&V2::new(60., 50.)) < 0.1);
+        assert!(
+            world.particles[1]
+                .position
+                .distance_to(&V2::new(150., 140.))
+                < 0.1
+        );
+        world.remove_pointer(1);
+        assert_eq!(world.particles[0].grab, None);
+        assert!(world.particles[1].grab.is_some());
+    }
+
+    #[test]
+    fn brushes_spawn_paint_and_erase() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(200., 200.), Gravity::Zero, 0);
+        world.push_particle(Particle::new(V2::new(20., 20.), V2::new(0., 0.)));
+        let center = V2::new(100., 100.);
+        world.set_tool(Tool::Spawn, 10.);
+        world.add_pointer(1, center, 1., Tool::Spawn);
+        world.apply_tools();
+        // the 5 x 5 lattice of the brush without its corners
+        assert_eq!(world.particles.len(), 1 + 21);
+        world.apply_tools();
+        assert_eq!(world.particles.len(), 1 + 21);
+        world.remove_pointer(1);
+
+        let red = Rgba::new(255, 0, 0, 255);
+        world.paint = Paint::Color(red);
+        world.add_pointer(2, center, 1., Tool::Paint);
+        world.apply_tools();
+        assert_eq!(world.particles[0].color, Rgba::WHITE);
+        assert!(world.particles[1..].iter().all(|p| p.color == red));
+        world.remove_pointer(2);
+
+        world.add_pointer(3, center, 1., Tool::Erase);
+        world.apply_tools();
+        assert_eq!(world.particles.len(), 1);
+        assert_eq!(world.particles[0].position, V2::new(20., 20.));
+    }
+
+    #[test]
+    fn pointer_fields_follow_their_pointer_and_are_not_user_removable() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(200., 200.), Gravity::Zero, 0);
+        let user_field = world.add_force_field(Box::new(RadialField {
+            center: V2::new(0., 0.),
+            radius: 10.,
+            strength: 1.,
+            falloff: 0.,
+        }));
+        world.add_pointer(1, V2::new(50., 50.), 1., Tool::Attract);
+        let field = world.pointers[&1].field.unwrap();
+        world.move_pointer(1, V2::new(120., 80.), 0.5);
+        assert_eq!(world.pointers[&1].field, Some(field));
+        assert_eq!(world.force_fields.len(), 2);
+        let probe = Particle::new(V2::new(130., 80.), V2::new(0., 0.));
+        let (_, pointer_field) = &world.force_fields[1];
+        assert!(pointer_field.acceleration(&probe, 0.).x < 0.);
+        // a smaller tool radius leaves the probe out of reach of the held pointer
+        world.set_tool(Tool::Attract, 5.);
+        let (_, pointer_field) = &world.force_fields[1];
+        assert_eq!(pointer_field.acceleration(&probe, 0.), V2::new(0., 0.));
+
+        assert!(!world.remove_force_field(field));
+        world.clear_force_fields();
+        assert_eq!(world.force_fields.len(), 1);
+        assert!(!world.remove_force_field(user_field));
+        world.remove_pointer(1);
+        assert!(world.force_fields.is_empty());
+    }
+}
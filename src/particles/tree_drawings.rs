@@ -1,16 +1,128 @@
-use kurbo::{Circle, Rect, Shape};
+use std::collections::BTreeMap;
+
+use kurbo::{BezPath, Circle, PathEl, Rect, Shape};
 use wasm_bindgen::JsValue;
 use web_sys::CanvasRenderingContext2d;
 
+use crate::error::FluidError;
+
 use super::{
+    attributes::Rgba,
+    emitters::Sink,
     hash_grid::HashGrid,
     hilbert_tree::{SpaceFillingCurve, SpaceFillingTree},
     particle::{GeoQuery, Particle, World, PARTICLE_RADIUS},
     quad_tree::QuadTree,
     rstar_tree::RStartree,
+    tools::MOUSE_POINTER,
     v2::{TreeValue, V2},
 };
 
+/// Half the side of the square drawn for a particle, inscribed in its circle.
+const PARTICLE_HALF_SIZE: f64 = PARTICLE_RADIUS / std::f64::consts::SQRT_2;
+
+/// The subset of the canvas 2d API the simulation draws with, implemented by the canvas
+/// itself and by frame packets that record the drawing to replay it elsewhere.
+pub trait Painter {
+    fn save(&mut self);
+    fn restore(&mut self);
+    fn set_fill_style(&mut self, color: &str);
+    fn set_stroke_style(&mut self, color: &str);
+    fn begin_path(&mut self);
+    fn move_to(&mut self, x: f64, y: f64);
+    fn line_to(&mut self, x: f64, y: f64);
+    fn quadratic_curve_to(&mut self, cx: f64, cy: f64, x: f64, y: f64);
+    fn bezier_curve_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64);
+    fn close_path(&mut self);
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64);
+    /// A full circle.
+    fn arc(&mut self, x: f64, y: f64, radius: f64) -> Result<(), FluidError>;
+    /// Squares of the given half side around the centers, added to the path.
+    fn squares(&mut self, centers: &[V2], half_size: f64);
+    fn fill(&mut self);
+    fn stroke(&mut self);
+    fn fill_text(&mut self, text: &str, x: f64, y: f64) -> Result<(), FluidError>;
+}
+
+impl Painter for CanvasRenderingContext2d {
+    fn save(&mut self) {
+        CanvasRenderingContext2d::save(self);
+    }
+
+    fn restore(&mut self) {
+        CanvasRenderingContext2d::restore(self);
+    }
+
+    fn set_fill_style(&mut self, color: &str) {
+        CanvasRenderingContext2d::set_fill_style(self, &JsValue::from_str(color));
+    }
+
+    fn set_stroke_style(&mut self, color: &str) {
+        CanvasRenderingContext2d::set_stroke_style(self, &JsValue::from_str(color));
+    }
+
+    fn begin_path(&mut self) {
+        CanvasRenderingContext2d::begin_path(self);
+    }
+
+    fn move_to(&mut self, x: f64, y: f64) {
+        CanvasRenderingContext2d::move_to(self, x, y);
+    }
+
+    fn line_to(&mut self, x: f64, y: f64) {
+        CanvasRenderingContext2d::line_to(self, x, y);
+    }
+
+    fn quadratic_curve_to(&mut self, cx: f64, cy: f64, x: f64, y: f64) {
+        CanvasRenderingContext2d::quadratic_curve_to(self, cx, cy, x, y);
+    }
+
+    fn bezier_curve_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) {
+        CanvasRenderingContext2d::bezier_curve_to(self, c1x, c1y, c2x, c2y, x, y);
+    }
+
+    fn close_path(&mut self) {
+        CanvasRenderingContext2d::close_path(self);
+    }
+
+    fn rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        CanvasRenderingContext2d::rect(self, x, y, width, height);
+    }
+
+    fn arc(&mut self, x: f64, y: f64, radius: f64) -> Result<(), FluidError> {
+        CanvasRenderingContext2d::arc(self, x, y, radius, 0., std::f64::consts::PI * 2.)
+            .map_err(canvas_error)
+    }
+
+    fn squares(&mut self, centers: &[V2], half_size: f64) {
+        centers.iter().for_each(|center| {
+            CanvasRenderingContext2d::rect(
+                self,
+                center.x - half_size,
+                center.y - half_size,
+                half_size * 2.,
+                half_size * 2.,
+            );
+        });
+    }
+
+    fn fill(&mut self) {
+        CanvasRenderingContext2d::fill(self);
+    }
+
+    fn stroke(&mut self) {
+        CanvasRenderingContext2d::stroke(self);
+    }
+
+    fn fill_text(&mut self, text: &str, x: f64, y: f64) -> Result<(), FluidError> {
+        CanvasRenderingContext2d::fill_text(self, text, x, y).map_err(canvas_error)
+    }
+}
+
+fn canvas_error(error: JsValue) -> FluidError {
+    FluidError::Canvas(error.as_string().unwrap_or_else(|| format!("{error:?}")))
+}
+
 pub struct DrawContext {
     pub mouse_pos: Option<V2>,
     pub mouse_radius: f64,
@@ -19,22 +131,24 @@ pub struct DrawContext {
 }
 
 pub trait Drawable {
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) -> Option<()>;
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError>;
 }
 
 impl<T: TreeValue> Drawable for QuadTree<T> {
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) -> Option<()> {
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError> {
         if let Some(mouse_pos) = draw_context.mouse_pos.as_ref() {
-            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius);
-            ctx.set_fill_style(&JsValue::from("red"));
+            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius)?;
+            ctx.set_fill_style("red");
             ctx.begin_path();
+            let mut near = Vec::new();
             self.query_distance(mouse_pos, draw_context.mouse_radius, |value| {
-                value.draw(ctx, draw_context);
+                near.push(value.position());
             });
+            ctx.squares(&near, PARTICLE_HALF_SIZE);
             ctx.fill();
 
             ctx.begin_path();
-            ctx.set_stroke_style(&JsValue::from("yellow"));
+            ctx.set_stroke_style("yellow");
             self.for_each(|node| {
                 let rect = node.get_rect();
                 ctx.rect(rect.x0, rect.y0, rect.width(), rect.height());
@@ -42,7 +156,7 @@ impl<T: TreeValue> Drawable for QuadTree<T> {
             ctx.stroke();
 
             ctx.begin_path();
-            ctx.set_stroke_style(&JsValue::from("red"));
+            ctx.set_stroke_style("red");
             self.query_distance_path(mouse_pos, draw_context.mouse_radius)
                 .into_iter()
                 .for_each(|node| {
@@ -51,14 +165,14 @@ impl<T: TreeValue> Drawable for QuadTree<T> {
                 });
             ctx.stroke();
         }
-        Some(())
+        Ok(())
     }
 }
 
 impl<T: SpaceFillingCurve> Drawable for SpaceFillingTree<T> {
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) -> Option<()> {
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError> {
         ctx.begin_path();
-        ctx.set_stroke_style(&JsValue::from("yellow"));
+        ctx.set_stroke_style("yellow");
         ctx.move_to(0., 0.);
         let num_of_points = self.number_of(draw_context.width * 2.0, draw_context.height * 2.0);
         (0..num_of_points).for_each(|i| {
@@ -73,7 +187,7 @@ impl<T: SpaceFillingCurve> Drawable for SpaceFillingTree<T> {
 
             let first = self.pair_of(lims.0);
             ctx.begin_path();
-            ctx.set_stroke_style(&JsValue::from("red"));
+            ctx.set_stroke_style("red");
             ctx.move_to(first.0, first.1);
             (lims.0..lims.1).for_each(|i| {
                 let (x, y) = self.pair_of(i);
@@ -81,109 +195,143 @@ impl<T: SpaceFillingCurve> Drawable for SpaceFillingTree<T> {
             });
             ctx.stroke();
 
-            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius);
-            ctx.set_fill_style(&JsValue::from("red"));
+            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius)?;
+            ctx.set_fill_style("red");
             ctx.begin_path();
+            let mut near = Vec::new();
             self.query_distance(mouse_pos, draw_context.mouse_radius, |value| {
-                value.draw(ctx, draw_context);
+                near.push(value.position());
             });
+            ctx.squares(&near, PARTICLE_HALF_SIZE);
             ctx.fill();
             let order = self.number_of(mouse_pos.x, mouse_pos.y);
-            ctx.fill_text(&format!("{order}"), 20.0, 20.0).unwrap();
+            ctx.fill_text(&format!("{order}"), 20.0, 20.0)?;
         }
-        Some(())
+        Ok(())
     }
 }
 
 impl<T: TreeValue> Drawable for RStartree<T> {
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) -> Option<()> {
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError> {
         let values = self.boundings();
         ctx.save();
-        ctx.set_stroke_style(&JsValue::from("white"));
+        ctx.set_stroke_style("white");
         ctx.begin_path();
         values.for_each(|rect: Rect| ctx.rect(rect.x0, rect.y0, rect.width(), rect.height()));
         ctx.stroke();
 
         if let Some(mouse_pos) = draw_context.mouse_pos.as_ref() {
-            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius);
-            ctx.set_fill_style(&JsValue::from("red"));
+            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius)?;
+            ctx.set_fill_style("red");
             ctx.begin_path();
+            let mut near = Vec::new();
             self.query_distance(mouse_pos, draw_context.mouse_radius, |value| {
-                value.draw(ctx, draw_context);
+                near.push(value.position());
             });
+            ctx.squares(&near, PARTICLE_HALF_SIZE);
             ctx.fill();
         }
         ctx.restore();
-        Some(())
+        Ok(())
     }
 }
 
 impl<T: TreeValue> Drawable for HashGrid<T> {
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) -> Option<()> {
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError> {
         ctx.begin_path();
-        ctx.set_stroke_style(&JsValue::from("white"));
+        ctx.set_stroke_style("white");
         self.get_rects().into_iter().for_each(|rect| {
             ctx.rect(rect.x0, rect.y0, rect.width(), rect.height());
         });
         ctx.stroke();
         if let Some(mouse_pos) = draw_context.mouse_pos.as_ref() {
-            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius);
-            ctx.set_fill_style(&JsValue::from("red"));
+            draw_mouse_range(ctx, mouse_pos, draw_context.mouse_radius)?;
+            ctx.set_fill_style("red");
             ctx.begin_path();
+            let mut near = Vec::new();
             self.query_distance(mouse_pos, draw_context.mouse_radius, |value| {
-                value.draw(ctx, draw_context);
+                near.push(value.position());
             });
+            ctx.squares(&near, PARTICLE_HALF_SIZE);
             ctx.fill();
         }
         ctx.restore();
-        Some(())
+        Ok(())
     }
 }
 
 impl<T: GeoQuery<Particle> + Drawable> Drawable for World<T> {
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) -> Option<()> {
+    fn draw(&self, ctx: &mut dyn Painter, draw_context: &DrawContext) -> Result<(), FluidError> {
         ctx.save();
-        ctx.begin_path();
-        ctx.set_fill_style(&JsValue::from_str("white"));
+        ctx.set_fill_style("gray");
+        self.obstacles.iter().for_each(|obstacle| {
+            ctx.begin_path();
+            draw_path(ctx, &obstacle.to_path());
+            ctx.fill();
+        });
+        if let Some(sdf) = self.sdf.as_ref() {
+            ctx.begin_path();
+            sdf.grid.solid_cells().for_each(|cell| {
+                ctx.rect(cell.x0, cell.y0, cell.width(), cell.height());
+            });
+            ctx.fill();
+        }
+        ctx.set_stroke_style("gray");
+        self.sinks.iter().for_each(|sink| {
+            ctx.begin_path();
+            match sink {
+                Sink::Circle(circle) => draw_path(ctx, &circle.to_path(0.1)),
+                Sink::Rect(rect) => draw_path(ctx, &rect.to_path(0.1)),
+            }
+            ctx.stroke();
+        });
+        if self.params.boundary_particles {
+            ctx.begin_path();
+            let positions: Vec<V2> = self.boundary.iter().map(|p| p.position).collect();
+            ctx.squares(&positions, PARTICLE_HALF_SIZE);
+            ctx.fill();
+        }
+        let mut by_color: BTreeMap<Rgba, Vec<V2>> = BTreeMap::new();
         self.particles.iter().for_each(|particle| {
-            particle.draw(ctx, draw_context);
+            by_color
+                .entry(particle.color)
+                .or_default()
+                .push(particle.position);
+        });
+        by_color.into_iter().for_each(|(color, positions)| {
+            ctx.begin_path();
+            ctx.set_fill_style(&color.to_css());
+            ctx.squares(&positions, PARTICLE_HALF_SIZE);
+            ctx.fill();
         });
-        ctx.fill();
         ctx.restore();
         // let center = V2::new(WIDTH as f64 / 2., HEIGHT as f64 / 2.);
         // let gradient = self.calc_gradient(&center);
         // draw_arrow(&center, &center.add(&gradient), ctx);
         if self.is_pressing_mouse {
-            self.tree.draw(ctx, draw_context);
+            self.tree.draw(ctx, draw_context)?;
         }
-        Some(())
+        self.pointers
+            .iter()
+            .filter(|(id, _)| **id != MOUSE_POINTER)
+            .try_for_each(|(_, pointer)| draw_mouse_range(ctx, &pointer.position, self.tool_radius))
     }
 }
 
-impl<T: TreeValue> Drawable for T {
-    fn draw(&self, ctx: &CanvasRenderingContext2d, draw_context: &DrawContext) -> Option<()> {
-        let position = self.position();
-        let sqrt_2 = 2.0_f64.sqrt();
-        ctx.rect(
-            position.x - PARTICLE_RADIUS / sqrt_2,
-            position.y - PARTICLE_RADIUS / sqrt_2,
-            PARTICLE_RADIUS / sqrt_2 * 2.0,
-            PARTICLE_RADIUS / sqrt_2 * 2.0,
-        );
-        Some(())
-    }
+fn draw_path(ctx: &mut dyn Painter, path: &BezPath) {
+    path.elements().iter().for_each(|el| match el {
+        PathEl::MoveTo(p) => ctx.move_to(p.x, p.y),
+        PathEl::LineTo(p) => ctx.line_to(p.x, p.y),
+        PathEl::QuadTo(c, p) => ctx.quadratic_curve_to(c.x, c.y, p.x, p.y),
+        PathEl::CurveTo(c1, c2, p) => ctx.bezier_curve_to(c1.x, c1.y, c2.x, c2.y, p.x, p.y),
+        PathEl::ClosePath => ctx.close_path(),
+    });
 }
 
-fn draw_mouse_range(ctx: &CanvasRenderingContext2d, mouse_pos: &V2, radius: f64) {
-    ctx.set_stroke_style(&JsValue::from("red"));
+fn draw_mouse_range(ctx: &mut dyn Painter, mouse_pos: &V2, radius: f64) -> Result<(), FluidError> {
+    ctx.set_stroke_style("red");
     ctx.begin_path();
-    ctx.arc(
-        mouse_pos.x,
-        mouse_pos.y,
-        radius,
-        0.,
-        std::f64::consts::PI * 2.,
-    )
-    .unwrap();
+    ctx.arc(mouse_pos.x, mouse_pos.y, radius)?;
     ctx.stroke();
+    Ok(())
 }
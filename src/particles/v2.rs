@@ -1,11 +1,27 @@
 use std::ops::{Add, Div, Mul};
 
-#[derive(Clone, Debug, PartialEq, Copy)]
+use serde::{Deserialize, Serialize};
+
+/// Written as `[x, y]` in scenes.
+#[derive(Clone, Debug, PartialEq, Copy, Default, Serialize, Deserialize)]
+#[serde(from = "[f64; 2]", into = "[f64; 2]")]
 pub struct V2 {
     pub x: f64,
     pub y: f64,
 }
 
+impl From<[f64; 2]> for V2 {
+    fn from([x, y]: [f64; 2]) -> V2 {
+        V2 { x, y }
+    }
+}
+
+impl From<V2> for [f64; 2] {
+    fn from(v: V2) -> [f64; 2] {
+        [v.x, v.y]
+    }
+}
+
 impl V2 {
     pub fn new(x: f64, y: f64) -> V2 {
         V2 { x, y }
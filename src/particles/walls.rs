@@ -0,0 +1,200 @@
+use super::{
+    obstacles,
+    params::{WallMode, WallParams},
+    particle::{GeoQuery, Particle, World, PARTICLE_RADIUS},
+    sph::SMOOTHING_RADIUS,
+    v2::V2,
+};
+
+/// An edge of the domain, from `from` to `to`, with the normal pointing inside.
+pub(super) struct Wall {
+    pub params: WallParams,
+    pub normal: V2,
+    pub from: V2,
+    pub to: V2,
+    /// Whether the wall is an edge of a periodic axis.
+    pub periodic: bool,
+}
+
+impl Wall {
+    /// Whether the wall keeps particles inside, rather than letting them through.
+    pub fn is_solid(&self) -> bool {
+        !self.periodic && self.params.mode != WallMode::Open
+    }
+
+    /// Signed distance of a point to the wall, negative outside the domain.
+    fn distance(&self, point: &V2) -> f64 {
+        let offset = point.sub(&self.from);
+        offset.x * self.normal.x + offset.y * self.normal.y
+    }
+}
+
+impl<T: GeoQuery<Particle>> World<T> {
+    fn is_periodic_x(&self) -> bool {
+        self.params.left_wall.mode == WallMode::Periodic
+            && self.params.right_wall.mode == WallMode::Periodic
+    }
+
+    fn is_periodic_y(&self) -> bool {
+        self.params.top_wall.mode == WallMode::Periodic
+            && self.params.bottom_wall.mode == WallMode::Periodic
+    }
+
+    pub(super) fn walls(&self) -> [Wall; 4] {
+        let (width, height) = (self.dimensions.x, self.dimensions.y);
+        let (periodic_x, periodic_y) = (self.is_periodic_x(), self.is_periodic_y());
+        [
+            Wall {
+                params: self.params.left_wall,
+                normal: V2::new(1., 0.),
+                from: V2::new(0., height),
+                to: V2::new(0., 0.),
+                periodic: periodic_x,
+            },
+            Wall {
+                params: self.params.right_wall,
+                normal: V2::new(-1., 0.),
+                from: V2::new(width, 0.),
+                to: V2::new(width, height),
+                periodic: periodic_x,
+            },
+            Wall {
+                params: self.params.top_wall,
+                normal: V2::new(0., 1.),
+                from: V2::new(0., 0.),
+                to: V2::new(width, 0.),
+                periodic: periodic_y,
+            },
+            Wall {
+                params: self.params.bottom_wall,
+                normal: V2::new(0., -1.),
+                from: V2::new(width, height),
+                to: V2::new(0., height),
+                periodic: periodic_y,
+            },
+        ]
+    }
+
+    pub(super) fn resolve_walls(&self, particle: &mut Particle) {
+        if self.is_periodic_x() {
+            particle.position.x = particle.position.x.rem_euclid(self.dimensions.x);
+        }
+        if self.is_periodic_y() {
+            particle.position.y = particle.position.y.rem_euclid(self.dimensions.y);
+        }
+        // keeps the fluid from sitting right on top of the wall boundary particles
+        let margin = if self.params.boundary_particles {
+            PARTICLE_RADIUS / 2.
+        } else {
+            0.
+        };
+        self.walls()
+            .into_iter()
+            .filter(|wall| wall.is_solid())
+            .for_each(|wall| {
+                let depth = wall.distance(&particle.position) - margin;
+                if depth >= 0. {
+                    return;
+                }
+                let surface = particle.position + (-depth) * wall.normal;
+                let (restitution, friction) = match wall.params.mode {
+                    WallMode::Absorbing => (0., 1.),
+                    _ => (wall.params.restitution, wall.params.friction),
+                };
+                obstacles::respond(particle, surface, wall.normal, restitution, friction);
+            });
+    }
+
+    /// Shortest displacement equivalent to `delta` through the periodic walls.
+    pub(super) fn periodic_offset(&self, mut delta: V2) -> V2 {
+        if self.is_periodic_x() {
+            let width = self.dimensions.x;
+            delta.x -= width * (delta.x / width).round();
+        }
+        if self.is_periodic_y() {
+            let height = self.dimensions.y;
+            delta.y -= height * (delta.y / height).round();
+        }
+        delta
+    }
+
+    /// Deletes the particles that left the domain through an open wall.
+    pub(super) fn remove_escaped(&mut self) {
+        let open: Vec<Wall> = self
+            .walls()
+            .into_iter()
+            .filter(|wall| !wall.periodic && wall.params.mode == WallMode::Open)
+            .collect();
+        if open.is_empty() {
+            return;
+        }
+        self.particles.retain(|particle| {
+            open.iter()
+                .all(|wall| wall.distance(&particle.position) >= 0.)
+        });
+    }
+
+    /// Copies of the particles close to periodic walls, moved to the other side of the
+    /// domain, so that neighbourhoods continue through the walls.
+    pub(super) fn periodic_ghosts(&self) -> Vec<Particle> {
+        let shifts = |periodic: bool, coordinate: f64, size: f64| {
+            let mut shifts = vec![0.];
+            if periodic && coordinate < SMOOTHING_RADIUS {
+                shifts.push(size);
+            }
+            if periodic && coordinate > size - SMOOTHING_RADIUS {
+                shifts.push(-size);
+            }
+            shifts
+        };
+        let (periodic_x, periodic_y) = (self.is_periodic_x(), self.is_periodic_y());
+        if !periodic_x && !periodic_y {
+            return Vec::new();
+        }
+        let mut ghosts = Vec::new();
+        self.particles.iter().for_each(|particle| {
+            let position = particle.position;
+            let xs = shifts(periodic_x, position.x, self.dimensions.x);
+            let ys = shifts(periodic_y, position.y, self.dimensions.y);
+            xs.iter()
+                .flat_map(|dx| ys.iter().map(move |dy| V2::new(*dx, *dy)))
+                .filter(|shift| shift.norm_sqr() > 0.)
+                .for_each(|shift| {
+                    let mut ghost = particle.clone();
+                    ghost.position = position + shift;
+                    ghosts.push(ghost);
+                });
+        });
+        ghosts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::particles::{gravity::Gravity, hash_grid::HashGrid};
+
+    #[test]
+    fn periodic_open_and_absorbing_walls() {
+        let mut world = World::<HashGrid<Particle>>::new(V2::new(100., 100.), Gravity::Zero, 0);
+        world.params.left_wall.mode = WallMode::Periodic;
+        assert!(world.params.validate().is_err());
+        world.params.right_wall.mode = WallMode::Periodic;
+        world.params.bottom_wall.mode = WallMode::Open;
+        assert!(world.params.validate().is_ok());
+        world.params.top_wall.mode = WallMode::Absorbing;
+        world.push_particle(Particle::new(V2::new(98., 20.), V2::new(300., 0.)));
+        world.push_particle(Particle::new(V2::new(50., 98.), V2::new(0., 300.)));
+        world.push_particle(Particle::new(V2::new(20., 2.), V2::new(100., -300.)));
+        assert_eq!(world.periodic_ghosts().len(), 1);
+
+        world.checked_evolve(1).unwrap();
+        // the one leaving through the bottom is gone
+        assert_eq!(world.particles.len(), 2);
+        let wrapped = &world.particles[0];
+        assert!(wrapped.position.x < 5. && wrapped.velocity.x > 0.);
+        let absorbed = &world.particles[1];
+        assert!(absorbed.position.y >= 0.);
+        assert_eq!(absorbed.velocity, V2::new(0., 0.));
+    }
+}
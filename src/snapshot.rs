@@ -0,0 +1,302 @@
+//! Versioned little endian binary format used to save and restore simulations.
+//!
+//! A snapshot starts with `MAGIC`, the format `VERSION` and the kind of simulation,
+//! followed by the fields of the simulation in a fixed order.
+
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"FLSN";
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapshotKind {
+    Fluid = 0,
+    Pendulum = 1,
+    Recording = 2,
+    Frame = 3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    WrongKind,
+    UnexpectedEnd,
+    TrailingBytes,
+    /// A value that can't be decoded, e.g. an unknown enum tag.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "unsupported snapshot version {version}, expected {VERSION}"
+                )
+            }
+            SnapshotError::WrongKind => write!(f, "snapshot of another kind of simulation"),
+            SnapshotError::UnexpectedEnd => write!(f, "snapshot is truncated"),
+            SnapshotError::TrailingBytes => write!(f, "unexpected bytes after the snapshot"),
+            SnapshotError::Invalid(what) => write!(f, "invalid {what} in snapshot"),
+        }
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, writer: &mut Writer);
+}
+
+pub trait Decode: Sized {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError>;
+}
+
+#[derive(Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new(kind: SnapshotKind) -> Writer {
+        let mut writer = Writer::default();
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.put(&VERSION);
+        writer.put(&(kind as u8));
+        writer
+    }
+
+    pub fn put<T: Encode + ?Sized>(&mut self, value: &T) {
+        value.encode(self);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the header of a snapshot and returns a reader of its fields.
+    pub fn new(bytes: &'a [u8], kind: SnapshotKind) -> Result<Reader<'a>, SnapshotError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let mut reader = Reader {
+            bytes: &bytes[MAGIC.len()..],
+        };
+        let version: u32 = reader.get()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        if reader.get::<u8>()? != kind as u8 {
+            return Err(SnapshotError::WrongKind);
+        }
+        Ok(reader)
+    }
+
+    pub fn get<T: Decode>(&mut self) -> Result<T, SnapshotError> {
+        T::decode(self)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        if self.bytes.len() < N {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+        let (head, tail) = self.bytes.split_at(N);
+        self.bytes = tail;
+        let mut value = [0; N];
+        value.copy_from_slice(head);
+        Ok(value)
+    }
+
+    /// Makes sure the whole snapshot was read.
+    pub fn finish(self) -> Result<(), SnapshotError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::TrailingBytes)
+        }
+    }
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, writer: &mut Writer) {
+                    writer.bytes.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+                    Ok(<$t>::from_le_bytes(reader.take()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(u8, u32, u64, f32, f64);
+
+impl Encode for usize {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&(*self as u64));
+    }
+}
+
+impl Decode for usize {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        usize::try_from(reader.get::<u64>()?).map_err(|_| SnapshotError::Invalid("length"))
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&(*self as u8));
+    }
+}
+
+impl Decode for bool {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Invalid("bool")),
+        }
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.len());
+        writer.bytes.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(self.as_str());
+    }
+}
+
+impl Decode for String {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        let len: usize = reader.get()?;
+        if reader.bytes.len() < len {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+        let (head, tail) = reader.bytes.split_at(len);
+        reader.bytes = tail;
+        String::from_utf8(head.to_vec()).map_err(|_| SnapshotError::Invalid("string"))
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.len());
+        self.iter().for_each(|value| writer.put(value));
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(self.as_slice());
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        let len: usize = reader.get()?;
+        // every value takes at least a byte, a larger length is a corrupt snapshot
+        if len > reader.bytes.len() {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+        (0..len).map(|_| reader.get()).collect()
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.is_some());
+        if let Some(value) = self {
+            writer.put(value);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        if reader.get()? {
+            Ok(Some(reader.get()?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.0);
+        writer.put(&self.1);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok((reader.get()?, reader.get()?))
+    }
+}
+
+/// Implements `Encode` and `Decode` for a struct by writing its fields in order.
+macro_rules! impl_struct {
+    ($t:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::snapshot::Encode for $t {
+            fn encode(&self, writer: &mut $crate::snapshot::Writer) {
+                $(writer.put(&self.$field);)*
+            }
+        }
+
+        impl $crate::snapshot::Decode for $t {
+            fn decode(
+                reader: &mut $crate::snapshot::Reader,
+            ) -> Result<Self, $crate::snapshot::SnapshotError> {
+                Ok($t {
+                    $($field: reader.get()?,)*
+                })
+            }
+        }
+    };
+}
+
+/// Implements `Encode` and `Decode` for a fieldless enum with a tag byte per variant.
+macro_rules! impl_unit_enum {
+    ($t:ident { $($variant:ident = $tag:literal),* $(,)? }) => {
+        impl $crate::snapshot::Encode for $t {
+            fn encode(&self, writer: &mut $crate::snapshot::Writer) {
+                let tag: u8 = match self {
+                    $($t::$variant => $tag,)*
+                };
+                writer.put(&tag);
+            }
+        }
+
+        impl $crate::snapshot::Decode for $t {
+            fn decode(
+                reader: &mut $crate::snapshot::Reader,
+            ) -> Result<Self, $crate::snapshot::SnapshotError> {
+                match reader.get::<u8>()? {
+                    $($tag => Ok($t::$variant),)*
+                    _ => Err($crate::snapshot::SnapshotError::Invalid(stringify!($t))),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use {impl_struct, impl_unit_enum};
@@ -2,6 +2,10 @@ use nalgebra::Vector2;
 use wasm_bindgen::prelude::*;
 use web_sys::CanvasRenderingContext2d;
 
+use crate::error::{finite, positive, FluidError};
+use crate::particles::Scene;
+use crate::snapshot::{impl_struct, Decode, Encode, Reader, SnapshotError, SnapshotKind, Writer};
+
 struct DynamicBall {
     mass: f64,
     radius: f64,
@@ -10,28 +14,21 @@ struct DynamicBall {
 }
 
 enum Ball {
-    FIXED { position: Vector2<f64> },
+    Fixed { position: Vector2<f64> },
     Dynamic(DynamicBall),
 }
 
 impl Ball {
     fn position(&self) -> Vector2<f64> {
         match self {
-            Ball::FIXED { position } => *position,
+            Ball::Fixed { position } => *position,
             Ball::Dynamic(ball) => ball.position,
         }
     }
 
-    fn radius(&self) -> f64 {
-        match self {
-            Ball::FIXED { .. } => 0.0,
-            Ball::Dynamic(ball) => ball.radius,
-        }
-    }
-
     fn w(&self) -> f64 {
         match self {
-            Ball::FIXED { .. } => 0.0,
+            Ball::Fixed { .. } => 0.0,
             Ball::Dynamic(ball) => 1.0 / ball.mass,
         }
     }
@@ -41,6 +38,54 @@ struct Link {
     length: f64,
 }
 
+impl Encode for Vector2<f64> {
+    fn encode(&self, writer: &mut Writer) {
+        writer.put(&self.x);
+        writer.put(&self.y);
+    }
+}
+
+impl Decode for Vector2<f64> {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Vector2::new(reader.get()?, reader.get()?))
+    }
+}
+
+impl_struct!(DynamicBall {
+    mass,
+    radius,
+    position,
+    velocity
+});
+impl_struct!(Link { length });
+
+impl Encode for Ball {
+    fn encode(&self, writer: &mut Writer) {
+        match self {
+            Ball::Fixed { position } => {
+                writer.put(&0u8);
+                writer.put(position);
+            }
+            Ball::Dynamic(ball) => {
+                writer.put(&1u8);
+                writer.put(ball);
+            }
+        }
+    }
+}
+
+impl Decode for Ball {
+    fn decode(reader: &mut Reader) -> Result<Self, SnapshotError> {
+        match reader.get::<u8>()? {
+            0 => Ok(Ball::Fixed {
+                position: reader.get()?,
+            }),
+            1 => Ok(Ball::Dynamic(reader.get()?)),
+            _ => Err(SnapshotError::Invalid("ball")),
+        }
+    }
+}
+
 const SCALE_FACTOR: f64 = 50.0;
 
 #[wasm_bindgen]
@@ -52,8 +97,15 @@ pub struct Pendulum {
 
 #[wasm_bindgen]
 impl Pendulum {
-    pub fn new(balls_num: usize, radius: f64) -> Self {
-        let fixed_ball = Ball::FIXED {
+    pub fn new(balls_num: usize, radius: f64) -> Result<Pendulum, FluidError> {
+        if balls_num == 0 {
+            return Err(FluidError::InvalidArgument {
+                name: "balls_num",
+                reason: "must be at least 1",
+            });
+        }
+        positive("radius", radius)?;
+        let fixed_ball = Ball::Fixed {
             position: Vector2::new(0.0, 0.0),
         };
         let mut balls = vec![fixed_ball];
@@ -69,29 +121,78 @@ impl Pendulum {
         let links = (0..balls_num)
             .map(|_| Link { length: 1.0 })
             .collect::<Vec<Link>>();
-        Self {
+        Ok(Pendulum {
             balls,
             links,
             next_fixed_ball_position: None,
+        })
+    }
+
+    /// Builds the pendulum at `index` in the `pendulums` of a JSON scene.
+    pub fn from_scene(scene: &str, index: usize) -> Result<Pendulum, FluidError> {
+        let scene = Scene::parse(scene)?;
+        let description = scene
+            .pendulums
+            .get(index)
+            .ok_or(FluidError::MissingPendulum(index))?;
+        let mut pendulum = Pendulum::new(description.balls, description.radius)?;
+        if let Some(anchor) = description.anchor {
+            pendulum.update_fixed_ball(anchor.x, anchor.y)?;
         }
+        Ok(pendulum)
+    }
+
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        let mut writer = Writer::new(SnapshotKind::Pendulum);
+        writer.put(&self.balls);
+        writer.put(&self.links);
+        writer.put(&self.next_fixed_ball_position);
+        writer.finish()
     }
 
-    pub fn update_fixed_ball(&mut self, x: f64, y: f64) {
+    /// Replaces the pendulum with one saved by `save_snapshot`.
+    pub fn load_snapshot(&mut self, bytes: &[u8]) -> Result<(), FluidError> {
+        Ok(self.restore(bytes)?)
+    }
+
+    pub fn update_fixed_ball(&mut self, x: f64, y: f64) -> Result<(), FluidError> {
+        finite("fixed ball", &[x, y])?;
         self.next_fixed_ball_position = Some(Vector2::new(x / SCALE_FACTOR, y / SCALE_FACTOR));
+        Ok(())
     }
 
-    pub fn evolve(&mut self, dt: f64, steps: usize) {
+    /// Advances by `dt` in `steps` steps. Fails if a ball stops being finite, in which
+    /// case the pendulum is left as it was before this call.
+    pub fn evolve(&mut self, dt: f64, steps: usize) -> Result<(), FluidError> {
+        positive("dt", dt)?;
+        let before = self.save_snapshot();
         let dt = dt / steps as f64;
-        for _ in 0..steps {
+        for step in 0..steps {
             self._evolve(dt);
+            let unstable = self.balls.iter().position(|ball| match ball {
+                Ball::Fixed { position } => !position.iter().all(|v| v.is_finite()),
+                Ball::Dynamic(ball) => !ball
+                    .position
+                    .iter()
+                    .chain(&ball.velocity)
+                    .all(|v| v.is_finite()),
+            });
+            if let Some(particle) = unstable {
+                self.restore(&before)?;
+                return Err(FluidError::Unstable {
+                    time: (step + 1) as f64 * dt,
+                    particle,
+                });
+            }
         }
+        Ok(())
     }
 
     fn _evolve(&mut self, dt: f64) {
         //update velocities
         self.balls.iter_mut().for_each(|ball| {
             match ball {
-                Ball::FIXED { .. } => {}
+                Ball::Fixed { .. } => {}
                 Ball::Dynamic(ball) => {
                     ball.velocity += Vector2::new(0.0, -9.8) * dt;
                 }
@@ -103,7 +204,7 @@ impl Pendulum {
             .iter()
             .map(|ball| {
                 match ball {
-                    Ball::FIXED { position } => {
+                    Ball::Fixed { position } => {
                         if let Some(next_fixed_ball_position) = self.next_fixed_ball_position {
                             next_fixed_ball_position
                         } else {
@@ -141,7 +242,7 @@ impl Pendulum {
         //update velocities and positions
         self.balls.iter_mut().enumerate().for_each(|(i, ball)| {
             match ball {
-                Ball::FIXED { ref mut position } => {
+                Ball::Fixed { ref mut position } => {
                     *position = new_positions[i];
                 }
                 Ball::Dynamic(ball) => {
@@ -171,3 +272,47 @@ impl Pendulum {
         });
     }
 }
+
+impl Pendulum {
+    fn restore(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = Reader::new(bytes, SnapshotKind::Pendulum)?;
+        let pendulum = Pendulum {
+            balls: reader.get()?,
+            links: reader.get()?,
+            next_fixed_ball_position: reader.get()?,
+        };
+        reader.finish()?;
+        if pendulum.links.len() + 1 != pendulum.balls.len() {
+            return Err(SnapshotError::Invalid("pendulum links"));
+        }
+        *self = pendulum;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_continues_the_same_trajectory() {
+        let mut pendulum = Pendulum::new(3, 0.1).unwrap();
+        pendulum.update_fixed_ball(20., 10.).unwrap();
+        pendulum.evolve(0.5, 20).unwrap();
+        let snapshot = pendulum.save_snapshot();
+        pendulum.evolve(0.5, 20).unwrap();
+
+        let mut restored = Pendulum::new(1, 0.1).unwrap();
+        restored.restore(&snapshot).unwrap();
+        restored.evolve(0.5, 20).unwrap();
+        let positions = |pendulum: &Pendulum| -> Vec<Vector2<f64>> {
+            pendulum.balls.iter().map(Ball::position).collect()
+        };
+        assert_eq!(positions(&restored), positions(&pendulum));
+        assert_eq!(
+            restored.restore(&snapshot[..snapshot.len() - 1]),
+            Err(SnapshotError::UnexpectedEnd)
+        );
+        assert!(Pendulum::new(0, 0.1).is_err());
+    }
+}
@@ -0,0 +1,44 @@
+import init, { CanvasDriven, CanvasDrivenArgs, TreeType } from "./pkg/fluid";
+
+// Runs the fluid off the main thread and posts a frame packet after every step.
+type Message =
+  | { type: "start"; width: number; height: number; particles: number }
+  | { type: "mouse"; x: number; y: number; isPressing: boolean }
+  | { type: "leave" }
+  | { type: "frame"; dt: number };
+
+let driven: CanvasDriven | undefined;
+
+onmessage = async (e: MessageEvent<Message>) => {
+  const message = e.data;
+  switch (message.type) {
+    case "start": {
+      await init();
+      const args = CanvasDrivenArgs.default();
+      args.width = message.width;
+      args.height = message.height;
+      args.tree_type = TreeType.RStar;
+      args.particles = message.particles;
+      driven = CanvasDriven.new(args);
+      postFrame(driven);
+      break;
+    }
+    case "mouse":
+      driven?.update_mouse_pos(message.x, message.y, message.isPressing);
+      break;
+    case "leave":
+      driven?.remove_mouse_pos();
+      break;
+    case "frame":
+      if (driven) {
+        driven.advance(message.dt);
+        postFrame(driven);
+      }
+      break;
+  }
+};
+
+function postFrame(driven: CanvasDriven) {
+  const packet = driven.frame_packet();
+  postMessage(packet, [packet.buffer]);
+}